@@ -0,0 +1,53 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use clap::Parser;
+use log::{error, info};
+use tello_autopilot::sim::{self, Simulator};
+use tokio::{net::UdpSocket, select, signal::ctrl_c};
+
+/// Simulated Tello speaking the SDK UDP protocol, for testing without a drone.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Command port of the drone
+    #[arg(long, default_value = "0.0.0.0:8889")]
+    listen: SocketAddr,
+    /// Port states are sent to, on the host that sent `command`
+    #[arg(long, default_value_t = 8890)]
+    state_port: u16,
+    /// Battery level at start in percent
+    #[arg(long, default_value_t = 100.0)]
+    battery: f64,
+    /// Log filter, e.g. `info` or `tello_autopilot=debug`
+    #[arg(long, default_value = "info")]
+    log_level: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    env_logger::Builder::new()
+        .parse_filters(&cli.log_level)
+        .init();
+
+    let mut simulator = Simulator::new();
+    simulator.set_battery(cli.battery);
+
+    let socket = UdpSocket::bind(cli.listen).await?;
+    info!("sim: Listening on {}", socket.local_addr()?);
+
+    select! {
+        res = sim::serve(socket, cli.state_port, Arc::new(Mutex::new(simulator))) => {
+            if let Err(e) = res {
+                error!("sim: {:?}", e);
+            }
+        }
+        _ = ctrl_c() => (),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Command line of `tello-autopilot`.
+
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::config::{Config, ConfigError};
+
+/// Autopilot for the DJI Tello. Options override the values in the config file.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub mode: Option<Mode>,
+    /// TOML config file
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Log filter, e.g. `info` or `tello_autopilot=debug`
+    #[arg(long)]
+    log_level: Option<String>,
+    /// Timeout for drone responses and states in milliseconds
+    #[arg(long)]
+    res_timeout_ms: Option<u64>,
+    /// Idle time before a keepalive command in milliseconds, 0 disables
+    #[arg(long)]
+    keepalive_ms: Option<u64>,
+    /// Command port (newline framing)
+    #[arg(long)]
+    listen_cmd: Option<SocketAddr>,
+    /// Command port (legacy `A` framing)
+    #[arg(long)]
+    listen_legacy_cmd: Option<SocketAddr>,
+    /// State stream port
+    #[arg(long)]
+    listen_state: Option<SocketAddr>,
+    /// JSON command port
+    #[arg(long)]
+    listen_rpc: Option<SocketAddr>,
+    /// Video subscriber control port
+    #[arg(long)]
+    listen_video_control: Option<SocketAddr>,
+    /// Recording control port
+    #[arg(long)]
+    listen_record_control: Option<SocketAddr>,
+    /// Safety event port
+    #[arg(long)]
+    listen_events: Option<SocketAddr>,
+    /// Drone command address
+    #[arg(long, env = "TELLO_CMD_ADDR")]
+    tello_cmd: Option<SocketAddr>,
+    /// Local address the drone sends states to
+    #[arg(long)]
+    tello_state: Option<SocketAddr>,
+    /// Local address the drone sends video to
+    #[arg(long)]
+    tello_video: Option<SocketAddr>,
+    /// Drone address poked to start the video stream
+    #[arg(long)]
+    tello_video_doorbell: Option<SocketAddr>,
+    /// Video destination, may be repeated; replaces the configured list
+    #[arg(long = "video-subscriber")]
+    video_subscribers: Vec<SocketAddr>,
+    /// Lifetime of video subscribers registered at runtime in milliseconds
+    #[arg(long)]
+    video_subscriber_ttl_ms: Option<u64>,
+    /// Directory the recordings are written to
+    #[arg(long)]
+    record_dir: Option<PathBuf>,
+    /// Start recording right away
+    #[arg(long)]
+    record: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Mode {
+    /// Play a pcap capture or a recording back as a fake drone
+    Replay(ReplayArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    /// pcap capture of the drone traffic, or a recording directory
+    pub source: PathBuf,
+    /// Playback speed, e.g. 2.0 plays twice as fast
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    pub speed: f64,
+    /// Where the states are sent
+    #[arg(long, default_value = "127.0.0.1:8890")]
+    pub state_target: SocketAddr,
+    /// Where the video is sent
+    #[arg(long, default_value = "127.0.0.1:11111")]
+    pub video_target: SocketAddr,
+    /// Start over when the end is reached
+    #[arg(long = "loop")]
+    pub repeat: bool,
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("{} is not a positive number", s)),
+    }
+}
+
+impl Cli {
+    /// The config file, if any, with the options applied over it.
+    pub fn into_config(self) -> Result<Config, ConfigError> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(v) = self.log_level {
+            config.log_level = v;
+        }
+        if let Some(v) = self.res_timeout_ms {
+            config.res_timeout_ms = v;
+        }
+        if let Some(v) = self.keepalive_ms {
+            config.keepalive_ms = v;
+        }
+        if let Some(v) = self.listen_cmd {
+            config.listen.cmd = v;
+        }
+        if let Some(v) = self.listen_legacy_cmd {
+            config.listen.legacy_cmd = v;
+        }
+        if let Some(v) = self.listen_state {
+            config.listen.state = v;
+        }
+        if let Some(v) = self.listen_rpc {
+            config.listen.rpc = v;
+        }
+        if let Some(v) = self.listen_video_control {
+            config.listen.video_control = v;
+        }
+        if let Some(v) = self.listen_record_control {
+            config.listen.record_control = v;
+        }
+        if let Some(v) = self.listen_events {
+            config.listen.events = v;
+        }
+        if let Some(v) = self.tello_cmd {
+            config.tello.cmd = v;
+        }
+        if let Some(v) = self.tello_state {
+            config.tello.state = v;
+        }
+        if let Some(v) = self.tello_video {
+            config.tello.video = v;
+        }
+        if let Some(v) = self.tello_video_doorbell {
+            config.tello.video_doorbell = v;
+        }
+        if !self.video_subscribers.is_empty() {
+            config.video.subscribers = self.video_subscribers;
+        }
+        if let Some(v) = self.video_subscriber_ttl_ms {
+            config.video.subscriber_ttl_ms = v;
+        }
+        if let Some(v) = self.record_dir {
+            config.record.dir = v;
+        }
+        if self.record {
+            config.record.autostart = true;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
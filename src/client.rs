@@ -0,0 +1,535 @@
+//! Typed client for flying the drone from Rust, either directly over the
+//! SDK's UDP port or through the proxy's command port.
+
+use std::{
+    fmt::{Display, Formatter},
+    net::SocketAddr,
+};
+
+use log::{info, warn};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream, UdpSocket,
+    },
+    spawn,
+    sync::{mpsc, oneshot},
+    time::{sleep, timeout, Duration, Instant},
+};
+
+use crate::{
+    cmd::{
+        Angle, Command, CommandResult, Coordinate, Distance, FlipCommandArg, OutOfRangeError,
+        RcValue, Speed,
+    },
+    proxy::TIMEOUT_REASON,
+};
+
+/// Where the commands go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// The drone's command port, e.g. `192.168.10.1:8889`
+    Udp(SocketAddr),
+    /// The proxy's command port (newline framing), e.g. `127.0.0.1:8988`.
+    /// The proxy gives up on the drone after its own `res_timeout_ms`, so
+    /// longer client timeouts have no effect.
+    Proxy(SocketAddr),
+}
+
+/// How often a command is sent again after a timeout. Only commands that
+/// do no harm when executed twice are retried, never moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first one
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            delay: Duration::from_millis(200),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// How long to wait for the response to a query or setting
+    pub timeout: Duration,
+    /// How long to wait for a motion; the drone answers when it is done.
+    /// Through the proxy, at most its `res_timeout_ms` (5s by default).
+    pub motion_timeout: Duration,
+    pub retry: RetryPolicy,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            motion_timeout: Duration::from_secs(30),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(std::io::Error),
+    OutOfRange(OutOfRangeError),
+    Timeout,
+    /// The drone answered with an error
+    Drone(String),
+    /// The response does not fit the command
+    Unexpected(String),
+    /// The connection is gone
+    Closed,
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::OutOfRange(e) => write!(f, "{}", e),
+            Self::Timeout => write!(f, "timed out waiting response"),
+            Self::Drone(res) => write!(f, "drone answered \"{}\"", res),
+            Self::Unexpected(res) => write!(f, "unexpected response \"{}\"", res),
+            Self::Closed => write!(f, "connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<OutOfRangeError> for ClientError {
+    fn from(e: OutOfRangeError) -> Self {
+        Self::OutOfRange(e)
+    }
+}
+
+struct ClientRequest {
+    cmd: Command,
+    timeout: Duration,
+    res_tx: oneshot::Sender<Result<Option<String>, ClientError>>,
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Proxy {
+        lines: Lines<BufReader<OwnedReadHalf>>,
+        writer: OwnedWriteHalf,
+        /// Responses still owed for commands that timed out here first
+        stale: usize,
+    },
+}
+
+impl Connection {
+    async fn request(
+        &mut self,
+        cmd: &Command,
+        res_timeout: Duration,
+    ) -> Result<Option<String>, ClientError> {
+        let deadline = Instant::now() + res_timeout;
+
+        match self {
+            Self::Udp(socket) => {
+                let mut buf = vec![0; 1024];
+
+                // drop stale responses
+                while let Ok(size) = socket.try_recv(&mut buf) {
+                    info!(
+                        "client: Drop stale response: {:?}",
+                        String::from_utf8_lossy(&buf[..size])
+                    );
+                }
+
+                socket.send(cmd.to_string().as_bytes()).await?;
+                if let Command::Rc { .. } = cmd {
+                    return Ok(None);
+                }
+
+                match timeout(res_timeout, socket.recv(&mut buf)).await {
+                    Ok(Ok(size)) => Ok(Some(String::from_utf8_lossy(&buf[..size]).to_string())),
+                    Ok(Err(e)) => Err(ClientError::Io(e)),
+                    Err(_) => Err(ClientError::Timeout),
+                }
+            }
+            Self::Proxy {
+                lines,
+                writer,
+                stale,
+            } => {
+                // the proxy answers every command eventually, keep the lines in step
+                while *stale > 0 {
+                    let line = next_line(lines, deadline).await?;
+                    info!("client: Drop stale response: {:?}", line);
+                    *stale -= 1;
+                }
+
+                writer.write_all(format!("{}\n", cmd).as_bytes()).await?;
+                if let Command::Rc { .. } = cmd {
+                    return Ok(None);
+                }
+
+                match next_line(lines, deadline).await {
+                    Ok(line) if line.strip_prefix("error ") == Some(TIMEOUT_REASON) => {
+                        Err(ClientError::Timeout)
+                    }
+                    Ok(line) => Ok(Some(line)),
+                    Err(ClientError::Timeout) => {
+                        *stale += 1;
+                        Err(ClientError::Timeout)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        }
+    }
+}
+
+async fn next_line(
+    lines: &mut Lines<BufReader<OwnedReadHalf>>,
+    deadline: Instant,
+) -> Result<String, ClientError> {
+    match tokio::time::timeout_at(deadline, lines.next_line()).await {
+        Ok(Ok(Some(line))) => Ok(line),
+        Ok(Ok(None)) => Err(ClientError::Closed),
+        Ok(Err(e)) => Err(ClientError::Io(e)),
+        Err(_) => Err(ClientError::Timeout),
+    }
+}
+
+// one command in flight at a time, in call order
+async fn serve_requests(mut conn: Connection, mut req_rx: mpsc::Receiver<ClientRequest>) {
+    while let Some(req) = req_rx.recv().await {
+        let res = conn.request(&req.cmd, req.timeout).await;
+        let closed = matches!(res, Err(ClientError::Closed));
+        let _ = req.res_tx.send(res);
+
+        if closed {
+            break;
+        }
+    }
+}
+
+/// Async client sending typed commands one at a time. Clones share the
+/// connection and its queue.
+///
+/// ```no_run
+/// # async fn fly() -> Result<(), tello_autopilot::client::ClientError> {
+/// use tello_autopilot::client::{TelloClient, Transport};
+///
+/// let client = TelloClient::connect(Transport::Proxy(([127, 0, 0, 1], 8988).into())).await?;
+/// client.takeoff().await?;
+/// client.forward(50).await?;
+/// println!("battery {}%", client.battery().await?);
+/// client.land().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TelloClient {
+    req_tx: mpsc::Sender<ClientRequest>,
+    options: ClientOptions,
+}
+
+impl TelloClient {
+    pub async fn connect(transport: Transport) -> Result<Self, ClientError> {
+        Self::connect_with(transport, ClientOptions::default()).await
+    }
+
+    pub async fn connect_with(
+        transport: Transport,
+        options: ClientOptions,
+    ) -> Result<Self, ClientError> {
+        let conn = match transport {
+            Transport::Udp(target) => {
+                let socket = UdpSocket::bind(("0.0.0.0", 0)).await?;
+                socket.connect(target).await?;
+                Connection::Udp(socket)
+            }
+            Transport::Proxy(target) => {
+                let (reader, writer) = TcpStream::connect(target).await?.into_split();
+                Connection::Proxy {
+                    lines: BufReader::new(reader).lines(),
+                    writer,
+                    stale: 0,
+                }
+            }
+        };
+
+        let (req_tx, req_rx) = mpsc::channel(32);
+        spawn(serve_requests(conn, req_rx));
+
+        Ok(Self { req_tx, options })
+    }
+
+    /// Sends `cmd` with the timeout for its kind. An error response is
+    /// returned as [`ClientError::Drone`]; `rc` has no response and gives
+    /// [`CommandResult::Ok`] once sent.
+    pub async fn send(&self, cmd: Command) -> Result<CommandResult, ClientError> {
+        let res_timeout = if is_motion(&cmd) {
+            self.options.motion_timeout
+        } else {
+            self.options.timeout
+        };
+        self.send_timeout(cmd, res_timeout).await
+    }
+
+    pub async fn send_timeout(
+        &self,
+        cmd: Command,
+        res_timeout: Duration,
+    ) -> Result<CommandResult, ClientError> {
+        let retries = if is_repeatable(&cmd) {
+            self.options.retry.retries
+        } else {
+            0
+        };
+
+        let mut attempt = 0;
+        let res = loop {
+            match self.request(cmd.clone(), res_timeout).await {
+                Err(ClientError::Timeout) if attempt < retries => {
+                    attempt += 1;
+                    warn!("client: {} timed out, retry {}/{}", cmd, attempt, retries);
+                    sleep(self.options.retry.delay).await;
+                }
+                res => break res?,
+            }
+        };
+
+        let res = match res {
+            Some(res) => res,
+            None => return Ok(CommandResult::Ok),
+        };
+
+        match CommandResult::from_response(&cmd, &res) {
+            CommandResult::Error => Err(ClientError::Drone(res.trim().to_string())),
+            result => Ok(result),
+        }
+    }
+
+    async fn request(
+        &self,
+        cmd: Command,
+        timeout: Duration,
+    ) -> Result<Option<String>, ClientError> {
+        let (res_tx, res_rx) = oneshot::channel();
+        let req = ClientRequest {
+            cmd,
+            timeout,
+            res_tx,
+        };
+
+        if self.req_tx.send(req).await.is_err() {
+            return Err(ClientError::Closed);
+        }
+
+        res_rx.await.unwrap_or(Err(ClientError::Closed))
+    }
+
+    async fn send_ok(&self, cmd: Command) -> Result<(), ClientError> {
+        match self.send(cmd).await? {
+            CommandResult::Ok => Ok(()),
+            res => Err(ClientError::Unexpected(format!("{:?}", res))),
+        }
+    }
+
+    /// Enters SDK mode, needed before anything else when flying over UDP.
+    pub async fn command(&self) -> Result<(), ClientError> {
+        self.send_ok(Command::Command).await
+    }
+
+    pub async fn takeoff(&self) -> Result<(), ClientError> {
+        self.send_ok(Command::Takeoff).await
+    }
+
+    pub async fn land(&self) -> Result<(), ClientError> {
+        self.send_ok(Command::Land).await
+    }
+
+    /// Stops the motors at once.
+    pub async fn emergency(&self) -> Result<(), ClientError> {
+        self.send_ok(Command::Emergency).await
+    }
+
+    pub async fn stream_on(&self) -> Result<(), ClientError> {
+        self.send_ok(Command::StreamOn).await
+    }
+
+    pub async fn stream_off(&self) -> Result<(), ClientError> {
+        self.send_ok(Command::StreamOff).await
+    }
+
+    pub async fn up(&self, cm: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::Up(Distance::new(cm)?)).await
+    }
+
+    pub async fn down(&self, cm: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::Down(Distance::new(cm)?)).await
+    }
+
+    pub async fn left(&self, cm: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::Left(Distance::new(cm)?)).await
+    }
+
+    pub async fn right(&self, cm: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::Right(Distance::new(cm)?)).await
+    }
+
+    pub async fn forward(&self, cm: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::Forward(Distance::new(cm)?)).await
+    }
+
+    pub async fn back(&self, cm: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::Back(Distance::new(cm)?)).await
+    }
+
+    pub async fn cw(&self, degrees: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::ClockwiseRotation(Angle::new(degrees)?))
+            .await
+    }
+
+    pub async fn ccw(&self, degrees: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::CounterClockwiseRotation(Angle::new(degrees)?))
+            .await
+    }
+
+    pub async fn flip(&self, direction: FlipCommandArg) -> Result<(), ClientError> {
+        self.send_ok(Command::Flip(direction)).await
+    }
+
+    /// Flies to `x`/`y`/`z` cm relative to the current position.
+    pub async fn go(&self, x: isize, y: isize, z: isize, speed: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::Go {
+            x: Coordinate::new(x)?,
+            y: Coordinate::new(y)?,
+            z: Coordinate::new(z)?,
+            speed: Speed::new(speed)?,
+            mid: None,
+        })
+        .await
+    }
+
+    /// Hovers in place.
+    pub async fn stop(&self) -> Result<(), ClientError> {
+        self.send_ok(Command::Stop).await
+    }
+
+    /// Sets the speed in cm/s for the moves.
+    pub async fn set_speed(&self, speed: usize) -> Result<(), ClientError> {
+        self.send_ok(Command::Speed(Speed::new(speed)?)).await
+    }
+
+    /// Remote control: left/right, forward/back, up/down and yaw, -99 ~ 99.
+    /// The drone does not answer.
+    pub async fn rc(&self, a: isize, b: isize, c: isize, d: isize) -> Result<(), ClientError> {
+        self.send_ok(Command::Rc {
+            a: RcValue::new(a)?,
+            b: RcValue::new(b)?,
+            c: RcValue::new(c)?,
+            d: RcValue::new(d)?,
+        })
+        .await
+    }
+
+    /// Battery percentage
+    pub async fn battery(&self) -> Result<u8, ClientError> {
+        match self.send(Command::ReadBattery).await? {
+            CommandResult::Battery(battery) => Ok(battery),
+            res => Err(ClientError::Unexpected(format!("{:?}", res))),
+        }
+    }
+
+    /// Speed setting in cm/s
+    pub async fn speed(&self) -> Result<f32, ClientError> {
+        match self.send(Command::ReadSpeed).await? {
+            CommandResult::Speed(speed) => Ok(speed),
+            res => Err(ClientError::Unexpected(format!("{:?}", res))),
+        }
+    }
+
+    /// Flight time in seconds
+    pub async fn time(&self) -> Result<usize, ClientError> {
+        match self.send(Command::ReadTime).await? {
+            CommandResult::Time(time) => Ok(time),
+            res => Err(ClientError::Unexpected(format!("{:?}", res))),
+        }
+    }
+
+    /// Wi-Fi SNR
+    pub async fn wifi(&self) -> Result<isize, ClientError> {
+        match self.send(Command::ReadWifi).await? {
+            CommandResult::Wifi(snr) => Ok(snr),
+            res => Err(ClientError::Unexpected(format!("{:?}", res))),
+        }
+    }
+
+    pub async fn sdk(&self) -> Result<String, ClientError> {
+        match self.send(Command::ReadSdk).await? {
+            CommandResult::Sdk(sdk) => Ok(sdk),
+            res => Err(ClientError::Unexpected(format!("{:?}", res))),
+        }
+    }
+
+    pub async fn serial_number(&self) -> Result<String, ClientError> {
+        match self.send(Command::ReadSerialNumber).await? {
+            CommandResult::SerialNumber(sn) => Ok(sn),
+            res => Err(ClientError::Unexpected(format!("{:?}", res))),
+        }
+    }
+}
+
+// the drone answers these when the motion is done
+fn is_motion(cmd: &Command) -> bool {
+    matches!(
+        cmd,
+        Command::Takeoff
+            | Command::Land
+            | Command::Up(_)
+            | Command::Down(_)
+            | Command::Left(_)
+            | Command::Right(_)
+            | Command::Forward(_)
+            | Command::Back(_)
+            | Command::ClockwiseRotation(_)
+            | Command::CounterClockwiseRotation(_)
+            | Command::Flip(_)
+            | Command::Go { .. }
+            | Command::Curve { .. }
+            | Command::Jump { .. }
+            | Command::ThrowFly
+    )
+}
+
+// sending these twice has the same effect as sending them once
+fn is_repeatable(cmd: &Command) -> bool {
+    matches!(
+        cmd,
+        Command::Command
+            | Command::Land
+            | Command::StreamOn
+            | Command::StreamOff
+            | Command::Emergency
+            | Command::Stop
+            | Command::Speed(_)
+            | Command::MissionpadOn
+            | Command::MissionpadOff
+            | Command::MissionpadDirection(_)
+            | Command::ReadSpeed
+            | Command::ReadBattery
+            | Command::ReadTime
+            | Command::ReadWifi
+            | Command::ReadSdk
+            | Command::ReadSerialNumber
+    )
+}
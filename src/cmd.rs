@@ -1,8 +1,399 @@
-use std::fmt::{Display, Formatter, Result};
+use std::{
+    fmt::{Display, Formatter, Result},
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use super::state::State;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutOfRangeError {
+    pub value: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Display for OutOfRangeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "{} is out of range, must be {} ~ {}",
+            self.value, self.min, self.max
+        )
+    }
+}
+
+impl std::error::Error for OutOfRangeError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidTextError {
+    pub value: String,
+    pub expected: &'static str,
+}
+
+impl Display for InvalidTextError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "\"{}\" is invalid, must be {}",
+            self.value, self.expected
+        )
+    }
+}
+
+impl std::error::Error for InvalidTextError {}
+
+/// Why a single argument could not be parsed, before the command it belongs
+/// to is known.
+enum ArgError {
+    NotANumber,
+    Invalid,
+    OutOfRange(OutOfRangeError),
+}
+
+/// A command argument that can be parsed from its SDK text form.
+trait Arg: Sized {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError>;
+}
+
+/// Declares a numeric command argument that can only hold values inside the
+/// range accepted by the SDK.
+macro_rules! bounded_arg {
+    ($(#[$meta:meta])* $name:ident($ty:ty), $min:expr, $max:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name($ty);
+
+        impl $name {
+            pub const MIN: $ty = $min;
+            pub const MAX: $ty = $max;
+
+            pub fn new(value: $ty) -> std::result::Result<Self, OutOfRangeError> {
+                if (Self::MIN..=Self::MAX).contains(&value) {
+                    Ok(Self(value))
+                } else {
+                    Err(OutOfRangeError {
+                        value: value as f64,
+                        min: Self::MIN as f64,
+                        max: Self::MAX as f64,
+                    })
+                }
+            }
+
+            pub fn get(self) -> $ty {
+                self.0
+            }
+        }
+
+        impl TryFrom<$ty> for $name {
+            type Error = OutOfRangeError;
+
+            fn try_from(value: $ty) -> std::result::Result<Self, Self::Error> {
+                Self::new(value)
+            }
+        }
+
+        impl From<$name> for $ty {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                self.0.serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<Self, D::Error> {
+                Self::new(<$ty>::deserialize(deserializer)?).map_err(de::Error::custom)
+            }
+        }
+
+        impl Arg for $name {
+            fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+                // parse wide so that e.g. "-5" is reported as out of range
+                let value: isize = s.parse().map_err(|_| ArgError::NotANumber)?;
+
+                if (Self::MIN as isize..=Self::MAX as isize).contains(&value) {
+                    Ok(Self(value as $ty))
+                } else {
+                    Err(ArgError::OutOfRange(OutOfRangeError {
+                        value: value as f64,
+                        min: Self::MIN as f64,
+                        max: Self::MAX as f64,
+                    }))
+                }
+            }
+        }
+    };
+}
+
+/// Declares a text command argument that is sent as a single token: its
+/// length in bytes and its characters are limited to what the SDK accepts,
+/// and it never contains whitespace.
+macro_rules! token_arg {
+    ($(#[$meta:meta])* $name:ident, $min:expr, $max:expr, $valid:expr, $expected:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub struct $name(String);
+
+        impl $name {
+            pub const MIN_LEN: usize = $min;
+            pub const MAX_LEN: usize = $max;
+
+            pub fn new(value: &str) -> std::result::Result<Self, InvalidTextError> {
+                let valid = (Self::MIN_LEN..=Self::MAX_LEN).contains(&value.len())
+                    && value.chars().all(|c| !c.is_whitespace() && $valid(c));
+
+                if valid {
+                    Ok(Self(value.to_string()))
+                } else {
+                    Err(InvalidTextError {
+                        value: value.to_string(),
+                        expected: $expected,
+                    })
+                }
+            }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl TryFrom<&str> for $name {
+            type Error = InvalidTextError;
+
+            fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+                Self::new(value)
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = InvalidTextError;
+
+            fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+                Self::new(&value)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                self.0.serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<Self, D::Error> {
+                Self::new(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+            }
+        }
+
+        impl Arg for $name {
+            fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+                Self::new(s).map_err(|_| ArgError::Invalid)
+            }
+        }
+    };
+}
+
+bounded_arg!(
+    /// Distance in cm for `up`/`down`/`left`/`right`/`forward`/`back`.
+    Distance(usize),
+    20,
+    500
+);
+bounded_arg!(
+    /// Rotation in degrees for `cw`/`ccw`.
+    Angle(usize),
+    1,
+    360
+);
+bounded_arg!(
+    /// Relative coordinate in cm for `go`/`curve`/`jump`.
+    Coordinate(isize),
+    -500,
+    500
+);
+bounded_arg!(
+    /// Speed in cm/s for `speed`/`go`/`jump`.
+    Speed(usize),
+    10,
+    100
+);
+bounded_arg!(
+    /// Speed in cm/s for `curve`, which is slower than the others.
+    CurveSpeed(usize),
+    10,
+    60
+);
+bounded_arg!(
+    /// Yaw in degrees for `jump`.
+    Yaw(usize),
+    0,
+    360
+);
+bounded_arg!(
+    /// Channel value for `rc`.
+    RcValue(isize),
+    -99,
+    99
+);
+bounded_arg!(
+    /// Mission pad id, written as `m1` ~ `m8` in commands.
+    MissionpadId(usize),
+    1,
+    8
+);
+
+bounded_arg!(
+    /// Video bitrate in Mbps for `setbitrate`, 0 means auto.
+    Bitrate(usize),
+    0,
+    5
+);
+bounded_arg!(
+    /// UDP port for `port`.
+    PortNumber(u16),
+    1025,
+    65535
+);
+
+token_arg!(
+    /// Network name for `wifi`/`ap`/`multwifi`, as long as an SSID may be.
+    Ssid,
+    1,
+    32,
+    |c: char| !c.is_control(),
+    "1 ~ 32 bytes without whitespace"
+);
+token_arg!(
+    /// WPA2 passphrase for `wifi`/`ap`/`multwifi`.
+    WifiPassword,
+    8,
+    63,
+    |c: char| c.is_ascii_graphic(),
+    "8 ~ 63 printable ASCII characters without spaces"
+);
+
+impl MissionpadId {
+    fn parse_prefixed(s: &str) -> std::result::Result<Self, ArgError> {
+        match s.strip_prefix('m') {
+            Some(s) => Self::parse_arg(s).map_err(|e| match e {
+                ArgError::NotANumber => ArgError::Invalid,
+                e => e,
+            }),
+            None => Err(ArgError::Invalid),
+        }
+    }
+}
+
+/// Blink/breath/scroll frequency in Hz for the expansion board.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Frequency(f64);
+
+impl Frequency {
+    pub const MIN: f64 = 0.1;
+    pub const MAX: f64 = 2.5;
+
+    pub fn new(value: f64) -> std::result::Result<Self, OutOfRangeError> {
+        if (Self::MIN..=Self::MAX).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(OutOfRangeError {
+                value,
+                min: Self::MIN,
+                max: Self::MAX,
+            })
+        }
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl Display for Frequency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Frequency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Frequency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Self::new(f64::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+impl Arg for Frequency {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        let value: f64 = s.parse().map_err(|_| ArgError::NotANumber)?;
+        Self::new(value).map_err(ArgError::OutOfRange)
+    }
+}
+
+impl Arg for u8 {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        let value: isize = s.parse().map_err(|_| ArgError::NotANumber)?;
+
+        u8::try_from(value).map_err(|_| {
+            ArgError::OutOfRange(OutOfRangeError {
+                value: value as f64,
+                min: 0.0,
+                max: 255.0,
+            })
+        })
+    }
+}
+
+impl Arg for bool {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        match s {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => Err(ArgError::Invalid),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FlipCommandArg {
     Left,
     Right,
@@ -11,6 +402,7 @@ pub enum FlipCommandArg {
 }
 
 impl FlipCommandArg {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "l" => Some(Self::Left),
@@ -22,6 +414,12 @@ impl FlipCommandArg {
     }
 }
 
+impl Arg for FlipCommandArg {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        Self::from_str(s).ok_or(ArgError::Invalid)
+    }
+}
+
 impl Display for FlipCommandArg {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         let s = match self {
@@ -35,7 +433,432 @@ impl Display for FlipCommandArg {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissionpadDirectionArg {
+    Downward,
+    Forward,
+    Both,
+}
+
+impl MissionpadDirectionArg {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "0" => Some(Self::Downward),
+            "1" => Some(Self::Forward),
+            "2" => Some(Self::Both),
+            _ => None,
+        }
+    }
+}
+
+impl Arg for MissionpadDirectionArg {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        Self::from_str(s).ok_or(ArgError::Invalid)
+    }
+}
+
+impl Display for MissionpadDirectionArg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let s = match self {
+            Self::Downward => "0",
+            Self::Forward => "1",
+            Self::Both => "2",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FpsArg {
+    Low,
+    Middle,
+    High,
+}
+
+impl FpsArg {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "low" => Some(Self::Low),
+            "middle" => Some(Self::Middle),
+            "high" => Some(Self::High),
+            _ => None,
+        }
+    }
+}
+
+impl Arg for FpsArg {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        Self::from_str(s).ok_or(ArgError::Invalid)
+    }
+}
+
+impl Display for FpsArg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let s = match self {
+            Self::Low => "low",
+            Self::Middle => "middle",
+            Self::High => "high",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+/// `low` is 480p and `high` is 720p.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolutionArg {
+    Low,
+    High,
+}
+
+impl ResolutionArg {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "low" => Some(Self::Low),
+            "high" => Some(Self::High),
+            _ => None,
+        }
+    }
+}
+
+impl Arg for ResolutionArg {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        Self::from_str(s).ok_or(ArgError::Invalid)
+    }
+}
+
+impl Display for ResolutionArg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let s = match self {
+            Self::Low => "low",
+            Self::High => "high",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Display for Rgb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{} {} {}", self.r, self.g, self.b)
+    }
+}
+
+/// Colors the LED matrix can show.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatrixColor {
+    Red,
+    Blue,
+    Purple,
+}
+
+impl MatrixColor {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "r" => Some(Self::Red),
+            "b" => Some(Self::Blue),
+            "p" => Some(Self::Purple),
+            _ => None,
+        }
+    }
+}
+
+impl Arg for MatrixColor {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        Self::from_str(s).ok_or(ArgError::Invalid)
+    }
+}
+
+impl Display for MatrixColor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let s = match self {
+            Self::Red => "r",
+            Self::Blue => "b",
+            Self::Purple => "p",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrollDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl ScrollDirection {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "l" => Some(Self::Left),
+            "r" => Some(Self::Right),
+            "u" => Some(Self::Up),
+            "d" => Some(Self::Down),
+            _ => None,
+        }
+    }
+}
+
+impl Arg for ScrollDirection {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        Self::from_str(s).ok_or(ArgError::Invalid)
+    }
+}
+
+impl Display for ScrollDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let s = match self {
+            Self::Left => "l",
+            Self::Right => "r",
+            Self::Up => "u",
+            Self::Down => "d",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+/// 8x8 LED matrix image, one of `r`/`b`/`p`/`0` per pixel row by row.
+/// Trailing pixels may be omitted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixPattern(String);
+
+impl MatrixPattern {
+    pub fn new(pattern: &str) -> std::result::Result<Self, InvalidTextError> {
+        let valid = (1..=64).contains(&pattern.len())
+            && pattern.chars().all(|c| matches!(c, 'r' | 'b' | 'p' | '0'));
+
+        if valid {
+            Ok(Self(pattern.to_string()))
+        } else {
+            Err(InvalidTextError {
+                value: pattern.to_string(),
+                expected: "1 to 64 of r, b, p or 0",
+            })
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Serialize for MatrixPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MatrixPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::new(&s).map_err(de::Error::custom)
+    }
+}
+
+impl Arg for MatrixPattern {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        Self::new(s).map_err(|_| ArgError::Invalid)
+    }
+}
+
+impl Display for MatrixPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Text scrolled on the LED matrix, up to 70 characters without whitespace.
 #[derive(Debug, Clone, PartialEq)]
+pub struct MatrixText(String);
+
+impl MatrixText {
+    pub fn new(text: &str) -> std::result::Result<Self, InvalidTextError> {
+        let valid =
+            (1..=70).contains(&text.chars().count()) && !text.chars().any(char::is_whitespace);
+
+        if valid {
+            Ok(Self(text.to_string()))
+        } else {
+            Err(InvalidTextError {
+                value: text.to_string(),
+                expected: "1 to 70 characters without whitespace",
+            })
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Serialize for MatrixText {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MatrixText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::new(&s).map_err(de::Error::custom)
+    }
+}
+
+impl Arg for MatrixText {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        Self::new(s).map_err(|_| ArgError::Invalid)
+    }
+}
+
+impl Display for MatrixText {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Character shown on the LED matrix with `mled s`, a letter or a digit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MatrixGlyph(char);
+
+impl MatrixGlyph {
+    pub fn new(ch: char) -> std::result::Result<Self, InvalidTextError> {
+        if ch.is_ascii_alphanumeric() {
+            Ok(Self(ch))
+        } else {
+            Err(InvalidTextError {
+                value: ch.to_string(),
+                expected: "an ASCII letter or digit",
+            })
+        }
+    }
+
+    pub fn get(self) -> char {
+        self.0
+    }
+}
+
+impl TryFrom<char> for MatrixGlyph {
+    type Error = InvalidTextError;
+
+    fn try_from(value: char) -> std::result::Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<MatrixGlyph> for char {
+    fn from(value: MatrixGlyph) -> Self {
+        value.0
+    }
+}
+
+impl Serialize for MatrixGlyph {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MatrixGlyph {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Self::new(char::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+impl Arg for MatrixGlyph {
+    fn parse_arg(s: &str) -> std::result::Result<Self, ArgError> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => Self::new(ch).map_err(|_| ArgError::Invalid),
+            _ => Err(ArgError::Invalid),
+        }
+    }
+}
+
+impl Display for MatrixGlyph {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Commands for the RoboMaster TT expansion board, sent as `EXT ...`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtCommand {
+    Led(Rgb),
+    LedBreath {
+        freq: Frequency,
+        color: Rgb,
+    },
+    LedBlink {
+        freq: Frequency,
+        color1: Rgb,
+        color2: Rgb,
+    },
+    MatrixPattern(MatrixPattern),
+    MatrixScroll {
+        direction: ScrollDirection,
+        color: MatrixColor,
+        freq: Frequency,
+        text: MatrixText,
+    },
+    MatrixChar {
+        color: MatrixColor,
+        ch: MatrixGlyph,
+    },
+    MatrixClear,
+    MatrixBrightness(u8),
+    ReadTof,
+}
+
+impl Display for ExtCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Led(color) => write!(f, "led {}", color),
+            Self::LedBreath { freq, color } => write!(f, "led br {} {}", freq, color),
+            Self::LedBlink {
+                freq,
+                color1,
+                color2,
+            } => write!(f, "led bl {} {} {}", freq, color1, color2),
+            Self::MatrixPattern(pattern) => write!(f, "mled g {}", pattern),
+            Self::MatrixScroll {
+                direction,
+                color,
+                freq,
+                text,
+            } => write!(f, "mled {} {} {} {}", direction, color, freq, text),
+            Self::MatrixChar { color, ch } => write!(f, "mled s {} {}", color, ch),
+            Self::MatrixClear => write!(f, "mled sc"),
+            Self::MatrixBrightness(value) => write!(f, "mled sl {}", value),
+            Self::ReadTof => write!(f, "tof?"),
+        }
+    }
+}
+
+/// A command for the Tello SDK. Arguments are range-checked when they are
+/// constructed, so every value can be sent to the drone as is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Command {
     Command,
     Takeoff,
@@ -43,59 +866,59 @@ pub enum Command {
     StreamOn,
     StreamOff,
     Emergency,
-    Up(usize),
-    Down(usize),
-    Left(usize),
-    Right(usize),
-    Forward(usize),
-    Back(usize),
-    ClockwiseRotation(usize),
-    CounterClockwiseRotation(usize),
+    Up(Distance),
+    Down(Distance),
+    Left(Distance),
+    Right(Distance),
+    Forward(Distance),
+    Back(Distance),
+    ClockwiseRotation(Angle),
+    CounterClockwiseRotation(Angle),
     Flip(FlipCommandArg),
     Go {
-        x: isize,
-        y: isize,
-        z: isize,
-        speed: usize,
-        mid: Option<usize>,
+        x: Coordinate,
+        y: Coordinate,
+        z: Coordinate,
+        speed: Speed,
+        mid: Option<MissionpadId>,
     },
     Stop,
     Curve {
-        x1: isize,
-        y1: isize,
-        z1: isize,
-        x2: isize,
-        y2: isize,
-        z2: isize,
-        speed: usize,
-        mid: Option<usize>,
+        x1: Coordinate,
+        y1: Coordinate,
+        z1: Coordinate,
+        x2: Coordinate,
+        y2: Coordinate,
+        z2: Coordinate,
+        speed: CurveSpeed,
+        mid: Option<MissionpadId>,
     },
     Jump {
-        x: isize,
-        y: isize,
-        z: isize,
-        speed: usize,
-        yaw: usize,
-        mid1: usize,
-        mid2: usize,
+        x: Coordinate,
+        y: Coordinate,
+        z: Coordinate,
+        speed: Speed,
+        yaw: Yaw,
+        mid1: MissionpadId,
+        mid2: MissionpadId,
     },
-    Speed(usize),
+    Speed(Speed),
     Rc {
-        a: isize,
-        b: isize,
-        c: isize,
-        d: isize,
+        a: RcValue,
+        b: RcValue,
+        c: RcValue,
+        d: RcValue,
     },
     Wifi {
-        ssid: String,
-        pass: String,
+        ssid: Ssid,
+        pass: WifiPassword,
     },
     MissionpadOn,
     MissionpadOff,
-    MissionpadDirection(usize),
+    MissionpadDirection(MissionpadDirectionArg),
     AccessPoint {
-        ssid: String,
-        pass: String,
+        ssid: Ssid,
+        pass: WifiPassword,
     },
     ReadSpeed,
     ReadBattery,
@@ -103,184 +926,376 @@ pub enum Command {
     ReadWifi,
     ReadSdk,
     ReadSerialNumber,
+    // SDK 3.0 (RoboMaster TT)
+    MotorOn,
+    MotorOff,
+    ThrowFly,
+    DownVision(bool),
+    SetFps(FpsArg),
+    SetBitrate(Bitrate),
+    SetResolution(ResolutionArg),
+    Port {
+        info: PortNumber,
+        video: PortNumber,
+    },
+    MultiWifi {
+        ssid: Ssid,
+        pass: WifiPassword,
+    },
+    Ext(ExtCommand),
 }
 
-impl Command {
-    pub fn from_str(s: &str) -> Option<Self> {
-        let parts: Vec<&str> = s.trim().split_whitespace().collect();
-
-        match parts.get(0) {
-            Some(&"command") => Some(Command::Command),
-            Some(&"takeoff") => Some(Command::Takeoff),
-            Some(&"land") => Some(Command::Land),
-            Some(&"streamon") => Some(Command::StreamOn),
-            Some(&"streamoff") => Some(Command::StreamOff),
-            Some(&"emergency") => Some(Command::Emergency),
-            Some(&"up") if parts.len() == 2 => {
-                let value = parts[1].parse().ok()?;
-                if value >= 20 && value <= 500 {
-                    Some(Command::Up(value))
-                } else {
-                    None
-                }
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandParseError {
+    Empty,
+    UnknownCommand(String),
+    WrongArity {
+        command: String,
+        expected: &'static str,
+        found: usize,
+    },
+    NotANumber {
+        command: String,
+        arg: String,
+    },
+    InvalidArgument {
+        command: String,
+        arg: String,
+    },
+    OutOfRange {
+        command: String,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+}
+
+impl Display for CommandParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Empty => write!(f, "empty command"),
+            Self::UnknownCommand(command) => write!(f, "unknown command \"{}\"", command),
+            Self::WrongArity {
+                command,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: expected {} argument(s), found {}",
+                command, expected, found
+            ),
+            Self::NotANumber { command, arg } => {
+                write!(f, "{}: \"{}\" is not a number", command, arg)
             }
-            Some(&"down") if parts.len() == 2 => {
-                let value = parts[1].parse().ok()?;
-                if value >= 20 && value <= 500 {
-                    Some(Command::Down(value))
-                } else {
-                    None
+            Self::InvalidArgument { command, arg } => {
+                write!(f, "{}: invalid argument \"{}\"", command, arg)
+            }
+            Self::OutOfRange {
+                command,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{}: {} is out of range, must be {} ~ {}",
+                command, value, min, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CommandParseError {}
+
+/// Arguments following the command verb, with helpers that report failures
+/// against that verb.
+struct Args<'a> {
+    command: &'a str,
+    args: &'a [&'a str],
+}
+
+impl<'a> Args<'a> {
+    fn expect(
+        &self,
+        counts: &[usize],
+        expected: &'static str,
+    ) -> std::result::Result<(), CommandParseError> {
+        if counts.contains(&self.args.len()) {
+            Ok(())
+        } else {
+            Err(CommandParseError::WrongArity {
+                command: self.command.to_string(),
+                expected,
+                found: self.args.len(),
+            })
+        }
+    }
+
+    fn arg<T: Arg>(&self, i: usize) -> std::result::Result<T, CommandParseError> {
+        T::parse_arg(self.args[i]).map_err(|e| self.error(i, e))
+    }
+
+    fn rgb(&self, i: usize) -> std::result::Result<Rgb, CommandParseError> {
+        Ok(Rgb {
+            r: self.arg(i)?,
+            g: self.arg(i + 1)?,
+            b: self.arg(i + 2)?,
+        })
+    }
+
+    fn missionpad_id(&self, i: usize) -> std::result::Result<MissionpadId, CommandParseError> {
+        MissionpadId::parse_prefixed(self.args[i]).map_err(|e| self.error(i, e))
+    }
+
+    fn optional_missionpad_id(
+        &self,
+        i: usize,
+    ) -> std::result::Result<Option<MissionpadId>, CommandParseError> {
+        if i < self.args.len() {
+            self.missionpad_id(i).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn error(&self, i: usize, e: ArgError) -> CommandParseError {
+        let command = self.command.to_string();
+        let arg = self.args[i].to_string();
+
+        match e {
+            ArgError::NotANumber => CommandParseError::NotANumber { command, arg },
+            ArgError::Invalid => CommandParseError::InvalidArgument { command, arg },
+            ArgError::OutOfRange(e) => CommandParseError::OutOfRange {
+                command,
+                value: e.value,
+                min: e.min,
+                max: e.max,
+            },
+        }
+    }
+}
+
+fn parse_ext(args: &Args) -> std::result::Result<ExtCommand, CommandParseError> {
+    args.expect(&[1, 2, 3, 4, 5, 6, 7, 8, 9], "1 ~ 9")?;
+
+    let command = format!("EXT {}", args.args[0]);
+    let args = Args {
+        command: &command,
+        args: &args.args[1..],
+    };
+
+    let cmd = match command.as_str() {
+        "EXT led" => {
+            args.expect(&[3, 5, 8], "3, 5 or 8")?;
+
+            match args.args.len() {
+                3 => ExtCommand::Led(args.rgb(0)?),
+                5 if args.args[0] == "br" => ExtCommand::LedBreath {
+                    freq: args.arg(1)?,
+                    color: args.rgb(2)?,
+                },
+                8 if args.args[0] == "bl" => ExtCommand::LedBlink {
+                    freq: args.arg(1)?,
+                    color1: args.rgb(2)?,
+                    color2: args.rgb(5)?,
+                },
+                _ => return Err(args.error(0, ArgError::Invalid)),
+            }
+        }
+        "EXT mled" => {
+            args.expect(&[1, 2, 3, 4], "1 ~ 4")?;
+
+            match (args.args[0], args.args.len()) {
+                ("g", 2) => ExtCommand::MatrixPattern(args.arg(1)?),
+                ("l" | "r" | "u" | "d", 4) => ExtCommand::MatrixScroll {
+                    direction: args.arg(0)?,
+                    color: args.arg(1)?,
+                    freq: args.arg(2)?,
+                    text: args.arg(3)?,
+                },
+                ("s", 3) => ExtCommand::MatrixChar {
+                    color: args.arg(1)?,
+                    ch: args.arg(2)?,
+                },
+                ("sc", 1) => ExtCommand::MatrixClear,
+                ("sl", 2) => ExtCommand::MatrixBrightness(args.arg(1)?),
+                _ => return Err(args.error(0, ArgError::Invalid)),
+            }
+        }
+        "EXT tof?" => {
+            args.expect(&[0], "0")?;
+            ExtCommand::ReadTof
+        }
+        _ => return Err(CommandParseError::UnknownCommand(command)),
+    };
+
+    Ok(cmd)
+}
+
+impl FromStr for Command {
+    type Err = CommandParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let (command, args) = match parts.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Err(CommandParseError::Empty),
+        };
+        let args = Args { command, args };
+
+        let cmd = match command {
+            "command" | "takeoff" | "land" | "streamon" | "streamoff" | "emergency" | "stop"
+            | "mon" | "moff" | "speed?" | "battery?" | "time?" | "wifi?" | "sdk?" | "sn?"
+            | "motoron" | "motoroff" | "throwfly" => {
+                args.expect(&[0], "0")?;
+
+                match command {
+                    "command" => Command::Command,
+                    "takeoff" => Command::Takeoff,
+                    "land" => Command::Land,
+                    "streamon" => Command::StreamOn,
+                    "streamoff" => Command::StreamOff,
+                    "emergency" => Command::Emergency,
+                    "stop" => Command::Stop,
+                    "mon" => Command::MissionpadOn,
+                    "moff" => Command::MissionpadOff,
+                    "speed?" => Command::ReadSpeed,
+                    "battery?" => Command::ReadBattery,
+                    "time?" => Command::ReadTime,
+                    "wifi?" => Command::ReadWifi,
+                    "sdk?" => Command::ReadSdk,
+                    "sn?" => Command::ReadSerialNumber,
+                    "motoron" => Command::MotorOn,
+                    "motoroff" => Command::MotorOff,
+                    _ => Command::ThrowFly,
                 }
             }
-            Some(&"left") if parts.len() == 2 => {
-                let value = parts[1].parse().ok()?;
-                if value >= 20 && value <= 500 {
-                    Some(Command::Left(value))
-                } else {
-                    None
+            "up" | "down" | "left" | "right" | "forward" | "back" => {
+                args.expect(&[1], "1")?;
+                let value = args.arg(0)?;
+
+                match command {
+                    "up" => Command::Up(value),
+                    "down" => Command::Down(value),
+                    "left" => Command::Left(value),
+                    "right" => Command::Right(value),
+                    "forward" => Command::Forward(value),
+                    _ => Command::Back(value),
                 }
             }
-            Some(&"right") if parts.len() == 2 => {
-                let value = parts[1].parse().ok()?;
-                if value >= 20 && value <= 500 {
-                    Some(Command::Right(value))
-                } else {
-                    None
+            "cw" | "ccw" => {
+                args.expect(&[1], "1")?;
+                let value = args.arg(0)?;
+
+                match command {
+                    "cw" => Command::ClockwiseRotation(value),
+                    _ => Command::CounterClockwiseRotation(value),
                 }
             }
-            Some(&"forward") if parts.len() == 2 => {
-                let value = parts[1].parse().ok()?;
-                if value >= 20 && value <= 500 {
-                    Some(Command::Forward(value))
-                } else {
-                    None
+            "flip" => {
+                args.expect(&[1], "1")?;
+                Command::Flip(args.arg(0)?)
+            }
+            "go" => {
+                args.expect(&[4, 5], "4 or 5")?;
+                Command::Go {
+                    x: args.arg(0)?,
+                    y: args.arg(1)?,
+                    z: args.arg(2)?,
+                    speed: args.arg(3)?,
+                    mid: args.optional_missionpad_id(4)?,
                 }
             }
-            Some(&"back") if parts.len() == 2 => {
-                let value = parts[1].parse().ok()?;
-                if value >= 20 && value <= 500 {
-                    Some(Command::Back(value))
-                } else {
-                    None
+            "curve" => {
+                args.expect(&[7, 8], "7 or 8")?;
+                Command::Curve {
+                    x1: args.arg(0)?,
+                    y1: args.arg(1)?,
+                    z1: args.arg(2)?,
+                    x2: args.arg(3)?,
+                    y2: args.arg(4)?,
+                    z2: args.arg(5)?,
+                    speed: args.arg(6)?,
+                    mid: args.optional_missionpad_id(7)?,
                 }
             }
-            Some(&"cw") if parts.len() == 2 => {
-                let value = parts[1].parse().ok()?;
-                if value >= 1 && value <= 360 {
-                    Some(Command::ClockwiseRotation(value))
-                } else {
-                    None
+            "jump" => {
+                args.expect(&[7], "7")?;
+                Command::Jump {
+                    x: args.arg(0)?,
+                    y: args.arg(1)?,
+                    z: args.arg(2)?,
+                    speed: args.arg(3)?,
+                    yaw: args.arg(4)?,
+                    mid1: args.missionpad_id(5)?,
+                    mid2: args.missionpad_id(6)?,
                 }
             }
-            Some(&"ccw") if parts.len() == 2 => {
-                let value = parts[1].parse().ok()?;
-                if value >= 1 && value <= 360 {
-                    Some(Command::CounterClockwiseRotation(value))
-                } else {
-                    None
+            "speed" => {
+                args.expect(&[1], "1")?;
+                Command::Speed(args.arg(0)?)
+            }
+            "rc" => {
+                args.expect(&[4], "4")?;
+                Command::Rc {
+                    a: args.arg(0)?,
+                    b: args.arg(1)?,
+                    c: args.arg(2)?,
+                    d: args.arg(3)?,
                 }
             }
-            Some(&"flip") if parts.len() == 2 => {
-                let arg = FlipCommandArg::from_str(parts[1])?;
-                Some(Command::Flip(arg))
-            }
-            Some(&"go") if parts.len() == 6 => {
-                let x = parts[1].parse().ok()?;
-                let y = parts[2].parse().ok()?;
-                let z = parts[3].parse().ok()?;
-                let speed = parts[4].parse().ok()?;
-                Some(Command::Go {
-                    x,
-                    y,
-                    z,
-                    speed,
-                    mid: None, // Note: Mid value not parsed
-                })
-            }
-            Some(&"stop") => Some(Command::Stop),
-            Some(&"curve") if parts.len() == 8 => {
-                let x1 = parts[1].parse().ok()?;
-                let y1 = parts[2].parse().ok()?;
-                let z1 = parts[3].parse().ok()?;
-                let x2 = parts[4].parse().ok()?;
-                let y2 = parts[5].parse().ok()?;
-                let z2 = parts[6].parse().ok()?;
-                let speed = parts[7].parse().ok()?;
-                Some(Command::Curve {
-                    x1,
-                    y1,
-                    z1,
-                    x2,
-                    y2,
-                    z2,
-                    speed,
-                    mid: None, // Note: Mid value not parsed
-                })
-            }
-            Some(&"jump") if parts.len() == 9 => {
-                // Note: Jump command not fully implemented
-                Some(Command::Jump {
-                    x: 0,
-                    y: 0,
-                    z: 0,
-                    speed: 0,
-                    yaw: 0,
-                    mid1: 0,
-                    mid2: 0,
-                })
-            }
-            Some(&"speed") if parts.len() == 2 => {
-                let value = parts[1].parse().ok()?;
-                if value >= 10 && value <= 100 {
-                    Some(Command::Speed(value))
-                } else {
-                    None
+            "wifi" => {
+                args.expect(&[2], "2")?;
+                Command::Wifi {
+                    ssid: args.arg(0)?,
+                    pass: args.arg(1)?,
                 }
             }
-            Some(&"wifi") if parts.len() == 3 => {
-                let ssid = parts[1].to_string();
-                let pass = parts[2].to_string();
-                Some(Command::Wifi { ssid, pass })
-            }
-            Some(&"mon") => Some(Command::MissionpadOn),
-            Some(&"moff") => Some(Command::MissionpadOff),
-            Some(&"ap") if parts.len() == 3 => {
-                let ssid = parts[1].to_string();
-                let pass = parts[2].to_string();
-                Some(Command::AccessPoint { ssid, pass })
-            }
-            Some(&"speed?") => Some(Command::ReadSpeed),
-            Some(&"battery?") => Some(Command::ReadBattery),
-            Some(&"time?") => Some(Command::ReadTime),
-            Some(&"wifi?") => Some(Command::ReadWifi),
-            Some(&"sdk?") => Some(Command::ReadSdk),
-            Some(&"sn?") => Some(Command::ReadSerialNumber),
-            Some(&"rc") => if parts.len() == 5 {
-                let a = parts[1].parse::<isize>();
-                let b = parts[2].parse::<isize>();
-                let c = parts[3].parse::<isize>();
-                let d = parts[4].parse::<isize>();
-
-                if a.is_err() || b.is_err() || c.is_err() || d.is_err() {
-                    return None;
+            "ap" => {
+                args.expect(&[2], "2")?;
+                Command::AccessPoint {
+                    ssid: args.arg(0)?,
+                    pass: args.arg(1)?,
                 }
-
-                let a = a.unwrap();
-                let b = b.unwrap();
-                let c = c.unwrap();
-                let d = d.unwrap();
-
-                if !(a > -100 && a < 100) || !(b > -100 && b < 100) || !(c > -100 && c < 100) || !(d > -100 && d < 100) {
-                    return None;
+            }
+            "mdirection" => {
+                args.expect(&[1], "1")?;
+                Command::MissionpadDirection(args.arg(0)?)
+            }
+            "downvision" => {
+                args.expect(&[1], "1")?;
+                Command::DownVision(args.arg(0)?)
+            }
+            "setfps" => {
+                args.expect(&[1], "1")?;
+                Command::SetFps(args.arg(0)?)
+            }
+            "setbitrate" => {
+                args.expect(&[1], "1")?;
+                Command::SetBitrate(args.arg(0)?)
+            }
+            "setresolution" => {
+                args.expect(&[1], "1")?;
+                Command::SetResolution(args.arg(0)?)
+            }
+            "port" => {
+                args.expect(&[2], "2")?;
+                Command::Port {
+                    info: args.arg(0)?,
+                    video: args.arg(1)?,
                 }
-
-                Some(Command::Rc { a, b, c, d })
             }
-            else {
-                None
+            "multwifi" => {
+                args.expect(&[2], "2")?;
+                Command::MultiWifi {
+                    ssid: args.arg(0)?,
+                    pass: args.arg(1)?,
+                }
             }
-            _ => None,
-        }
+            "EXT" => Command::Ext(parse_ext(&args)?),
+            _ => return Err(CommandParseError::UnknownCommand(command.to_string())),
+        };
+
+        Ok(cmd)
     }
 }
 
@@ -293,62 +1308,14 @@ impl Display for Command {
             Self::StreamOn => "streamon".to_string(),
             Self::StreamOff => "streamoff".to_string(),
             Self::Emergency => "emergency".to_string(),
-            Self::Up(value) => {
-                if !(*value >= 20 && *value <= 500) {
-                    panic!("Not allowed argument: {:?}, must be 20 ~ 500", self);
-                }
-
-                format!("up {}", value)
-            }
-            Self::Down(value) => {
-                if !(*value >= 20 && *value <= 500) {
-                    panic!("Not allowed argument: {:?}, must be 20 ~ 500", self);
-                }
-
-                format!("down {}", value)
-            }
-            Self::Left(value) => {
-                if !(*value >= 20 && *value <= 500) {
-                    panic!("Not allowed argument: {:?}, must be 20 ~ 500", self);
-                }
-
-                format!("left {}", value)
-            }
-            Self::Right(value) => {
-                if !(*value >= 20 && *value <= 500) {
-                    panic!("Not allowed argument: {:?}, must be 20 ~ 500", self);
-                }
-
-                format!("right {}", value)
-            }
-            Self::Forward(value) => {
-                if !(*value >= 20 && *value <= 500) {
-                    panic!("Not allowed argument: {:?}, must be 20 ~ 500", self);
-                }
-
-                format!("forward {}", value)
-            }
-            Self::Back(value) => {
-                if !(*value >= 20 && *value <= 500) {
-                    panic!("Not allowed argument: {:?}, must be 20 ~ 500", self);
-                }
-
-                format!("back {}", value)
-            }
-            Self::ClockwiseRotation(value) => {
-                if !(*value >= 1 && *value <= 360) {
-                    panic!("Not allowed argument: {:?}, must be 1 ~ 360", self);
-                }
-
-                format!("cw {}", value)
-            }
-            Self::CounterClockwiseRotation(value) => {
-                if !(*value >= 1 && *value <= 360) {
-                    panic!("Not allowed argument: {:?}, must be 1 ~ 360", self);
-                }
-
-                format!("ccw {}", value)
-            }
+            Self::Up(value) => format!("up {}", value),
+            Self::Down(value) => format!("down {}", value),
+            Self::Left(value) => format!("left {}", value),
+            Self::Right(value) => format!("right {}", value),
+            Self::Forward(value) => format!("forward {}", value),
+            Self::Back(value) => format!("back {}", value),
+            Self::ClockwiseRotation(value) => format!("cw {}", value),
+            Self::CounterClockwiseRotation(value) => format!("ccw {}", value),
             Self::Flip(value) => format!("flip {}", value),
             Self::Go {
                 x,
@@ -356,28 +1323,10 @@ impl Display for Command {
                 z,
                 speed,
                 mid,
-            } => {
-                if !(*x >= -500 && *x <= 500) {
-                    panic!("Not allowed argument (x): {:?}, must be -500 ~ 500", self);
-                }
-
-                if !(*y >= -500 && *y <= 500) {
-                    panic!("Not allowed argument (y): {:?}, must be -500 ~ 500", self);
-                }
-
-                if !(*z >= -500 && *z <= 500) {
-                    panic!("Not allowed argument (z): {:?}, must be -500 ~ 500", self);
-                }
-
-                if !(*speed >= 10 && *speed <= 100) {
-                    panic!("Not allowed argument (speed): {:?}, must be 10 ~ 100", self);
-                }
-
-                match mid {
-                    Some(_) => unimplemented!(), // need missionpad
-                    None => format!("go {} {} {} {}", x, y, z, speed),
-                }
-            }
+            } => match mid {
+                Some(mid) => format!("go {} {} {} {} m{}", x, y, z, speed, mid),
+                None => format!("go {} {} {} {}", x, y, z, speed),
+            },
             Self::Stop => "stop".to_string(),
             Self::Curve {
                 x1,
@@ -388,72 +1337,31 @@ impl Display for Command {
                 z2,
                 speed,
                 mid,
-            } => {
-                if !(*x1 >= -500 && *x1 <= 500) {
-                    panic!("Not allowed argument (x1): {:?}, must be -500 ~ 500", self);
-                }
-
-                if !(*y1 >= -500 && *y1 <= 500) {
-                    panic!("Not allowed argument (y1): {:?}, must be -500 ~ 500", self);
-                }
-
-                if !(*z1 >= -500 && *z1 <= 500) {
-                    panic!("Not allowed argument (z1): {:?}, must be -500 ~ 500", self);
-                }
-
-                if !(*x2 >= -500 && *x2 <= 500) {
-                    panic!("Not allowed argument (x2): {:?}, must be -500 ~ 500", self);
-                }
-
-                if !(*y2 >= -500 && *y2 <= 500) {
-                    panic!("Not allowed argument (y2): {:?}, must be -500 ~ 500", self);
-                }
-
-                if !(*z2 >= -500 && *z2 <= 500) {
-                    panic!("Not allowed argument (z2): {:?}, must be -500 ~ 500", self);
-                }
-
-                if !(*speed >= 10 && *speed <= 100) {
-                    panic!("Not allowed argument (speed): {:?}, must be 10 ~ 100", self);
-                }
-
-                match mid {
-                    Some(_) => unimplemented!(), // need missionpad
-                    None => format!("curve {} {} {} {} {} {} {}", x1, y1, z1, x2, y2, z2, speed),
-                }
-            }
-            Self::Jump {
-                x: _,
-                y: _,
-                z: _,
-                speed: _,
-                yaw: _,
-                mid1: _,
-                mid2: _,
-            } => unimplemented!(), // need missionpad
-            Self::Speed(value) => {
-                if !(*value >= 10 && *value <= 100) {
-                    panic!("Not allowed argument: {:?}, must be 10 ~ 100", self);
-                }
-
-                format!("speed {}", value)
-            }
-            Self::Rc {
-                a,
-                b,
-                c,
-                d,
-            } => {
-                if !(*a > -100 && *a < 100) || !(*b > -100 && *b < 100) || !(*c > -100 && *c < 100) || !(*d > -100 && *d < 100) {
-                    panic!("Not allow argument : {:?}, must be -99 ~ 99", self);
-                }
-
-                format!("rc {} {} {} {}", a, b, c, d)
+            } => match mid {
+                Some(mid) => format!(
+                    "curve {} {} {} {} {} {} {} m{}",
+                    x1, y1, z1, x2, y2, z2, speed, mid
+                ),
+                None => format!("curve {} {} {} {} {} {} {}", x1, y1, z1, x2, y2, z2, speed),
             },
+            Self::Jump {
+                x,
+                y,
+                z,
+                speed,
+                yaw,
+                mid1,
+                mid2,
+            } => format!(
+                "jump {} {} {} {} {} m{} m{}",
+                x, y, z, speed, yaw, mid1, mid2
+            ),
+            Self::Speed(value) => format!("speed {}", value),
+            Self::Rc { a, b, c, d } => format!("rc {} {} {} {}", a, b, c, d),
             Self::Wifi { ssid, pass } => format!("wifi {} {}", ssid, pass),
             Self::MissionpadOn => "mon".to_string(),
             Self::MissionpadOff => "moff".to_string(),
-            Self::MissionpadDirection(_) => todo!(),
+            Self::MissionpadDirection(value) => format!("mdirection {}", value),
             Self::AccessPoint { ssid, pass } => format!("ap {} {}", ssid, pass),
             Self::ReadSpeed => "speed?".to_string(),
             Self::ReadBattery => "battery?".to_string(),
@@ -461,29 +1369,86 @@ impl Display for Command {
             Self::ReadWifi => "wifi?".to_string(),
             Self::ReadSdk => "sdk?".to_string(),
             Self::ReadSerialNumber => "sn?".to_string(),
+            Self::MotorOn => "motoron".to_string(),
+            Self::MotorOff => "motoroff".to_string(),
+            Self::ThrowFly => "throwfly".to_string(),
+            Self::DownVision(on) => format!("downvision {}", u8::from(*on)),
+            Self::SetFps(value) => format!("setfps {}", value),
+            Self::SetBitrate(value) => format!("setbitrate {}", value),
+            Self::SetResolution(value) => format!("setresolution {}", value),
+            Self::Port { info, video } => format!("port {} {}", info, video),
+            Self::MultiWifi { ssid, pass } => format!("multwifi {} {}", ssid, pass),
+            Self::Ext(cmd) => format!("EXT {}", cmd),
         };
 
         write!(f, "{}", s)
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// A response from the drone. Query answers are parsed into typed values
+/// according to the command that was sent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CommandResult {
     Ok,
     Error,
     State(State),
+    /// Battery percentage
+    Battery(u8),
+    /// Speed in cm/s
+    Speed(f32),
+    /// Flight time in seconds
+    Time(usize),
+    /// Wi-Fi SNR
+    Wifi(isize),
+    Sdk(String),
+    SerialNumber(String),
+    /// Distance from the expansion board ToF sensor in mm
+    Tof(usize),
     Other(String),
 }
 
 impl CommandResult {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
-        match s {
+        match s.trim() {
             "ok" => CommandResult::Ok,
-            "error" => CommandResult::Error,
+            s if s == "error" || s.starts_with("error ") => CommandResult::Error,
             s => match State::from_str(s) {
                 Some(state) => CommandResult::State(state),
                 None => CommandResult::Other(s.to_string()),
             },
         }
     }
+
+    /// Parses `s` as the response to `cmd`. Falls back to [`Self::from_str`]
+    /// when the response does not look like an answer to the query.
+    pub fn from_response(cmd: &Command, s: &str) -> Self {
+        let value = s.trim();
+        // free text, unless `from_str` reads it as something else
+        let text = || match Self::from_str(value) {
+            CommandResult::Other(v) if !v.is_empty() => Some(v),
+            _ => None,
+        };
+
+        let result = match cmd {
+            Command::ReadBattery => value.parse().ok().map(CommandResult::Battery),
+            Command::ReadSpeed => value.parse().ok().map(CommandResult::Speed),
+            Command::ReadTime => value
+                .trim_end_matches('s')
+                .parse()
+                .ok()
+                .map(CommandResult::Time),
+            Command::ReadWifi => value.parse().ok().map(CommandResult::Wifi),
+            Command::ReadSdk => text().map(CommandResult::Sdk),
+            Command::ReadSerialNumber => text().map(CommandResult::SerialNumber),
+            Command::Ext(ExtCommand::ReadTof) => value
+                .strip_prefix("tof ")
+                .and_then(|v| v.parse().ok())
+                .map(CommandResult::Tof),
+            _ => None,
+        };
+
+        result.unwrap_or_else(|| Self::from_str(value))
+    }
 }
@@ -0,0 +1,271 @@
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{geofence::FenceArea, safety::SafetyAction, video::MAX_SUBSCRIBER_TTL};
+
+/// Runtime configuration, read from a TOML file. Every field is optional
+/// and falls back to the defaults for a Tello on its own Wi-Fi.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Filter for env_logger, e.g. `info` or `tello_autopilot=debug`
+    pub log_level: String,
+    /// How long to wait for a response or a state from the drone
+    pub res_timeout_ms: u64,
+    /// Idle time after which the proxy sends `battery?` so the drone does
+    /// not land on its own after 15s without commands, 0 to disable
+    pub keepalive_ms: u64,
+    pub listen: ListenConfig,
+    pub tello: TelloConfig,
+    pub video: VideoConfig,
+    pub record: RecordConfig,
+    pub safety: SafetyConfig,
+    pub geofence: GeofenceConfig,
+    pub flight: FlightConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_string(),
+            res_timeout_ms: 5000,
+            keepalive_ms: 10000,
+            listen: ListenConfig::default(),
+            tello: TelloConfig::default(),
+            video: VideoConfig::default(),
+            record: RecordConfig::default(),
+            safety: SafetyConfig::default(),
+            geofence: GeofenceConfig::default(),
+            flight: FlightConfig::default(),
+        }
+    }
+}
+
+/// Local addresses served to clients.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenConfig {
+    pub cmd: SocketAddr,
+    pub legacy_cmd: SocketAddr,
+    pub state: SocketAddr,
+    pub rpc: SocketAddr,
+    pub video_control: SocketAddr,
+    pub record_control: SocketAddr,
+    pub events: SocketAddr,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            cmd: ([127, 0, 0, 1], 8988).into(),
+            legacy_cmd: ([127, 0, 0, 1], 8989).into(),
+            state: ([127, 0, 0, 1], 8990).into(),
+            rpc: ([127, 0, 0, 1], 8991).into(),
+            video_control: ([127, 0, 0, 1], 8992).into(),
+            record_control: ([127, 0, 0, 1], 8993).into(),
+            events: ([127, 0, 0, 1], 8994).into(),
+        }
+    }
+}
+
+/// Addresses of the drone, and the local ports it sends to.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelloConfig {
+    pub cmd: SocketAddr,
+    pub state: SocketAddr,
+    pub video: SocketAddr,
+    pub video_doorbell: SocketAddr,
+}
+
+impl Default for TelloConfig {
+    fn default() -> Self {
+        Self {
+            cmd: ([192, 168, 10, 1], 8889).into(),
+            state: ([0, 0, 0, 0], 8890).into(),
+            video: ([0, 0, 0, 0], 11111).into(),
+            video_doorbell: ([192, 168, 10, 1], 62512).into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VideoConfig {
+    /// UDP addresses the video is always relayed to
+    pub subscribers: Vec<SocketAddr>,
+    /// Lifetime of subscribers registered over the control port unless renewed
+    pub subscriber_ttl_ms: u64,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        Self {
+            subscribers: vec![
+                ([127, 0, 0, 1], 11112).into(), // watchdog
+                ([127, 0, 0, 1], 11113).into(), // detector
+            ],
+            subscriber_ttl_ms: 30000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordConfig {
+    /// Each recording is a subdirectory of this one
+    pub dir: PathBuf,
+    /// Start recording right away instead of waiting for a request
+    pub autostart: bool,
+}
+
+impl Default for RecordConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("recordings"),
+            autostart: false,
+        }
+    }
+}
+
+/// Failsafe thresholds and what happens when one is crossed.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SafetyConfig {
+    /// Battery percentage below which `battery_action` is taken, 0 disables
+    pub min_battery: usize,
+    pub battery_action: SafetyAction,
+    /// Highest allowed `temph` in °C
+    pub max_temperature: usize,
+    pub temperature_action: SafetyAction,
+    /// How long the states may stop coming, 0 disables
+    pub state_timeout_ms: u64,
+    pub state_timeout_action: SafetyAction,
+    /// How long the drone may fly without a command client before
+    /// `link_lost_action`, so clients can reconnect; 0 acts right away
+    pub link_lost_grace_ms: u64,
+    /// Taken when the last command client disconnects while airborne
+    pub link_lost_action: SafetyAction,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            min_battery: 10,
+            battery_action: SafetyAction::Land,
+            max_temperature: 90,
+            temperature_action: SafetyAction::Land,
+            state_timeout_ms: 3000,
+            state_timeout_action: SafetyAction::Land,
+            link_lost_grace_ms: 5000,
+            link_lost_action: SafetyAction::Land,
+        }
+    }
+}
+
+/// Where the drone may fly, around the point of the first takeoff: `x` is
+/// forward and `y` left at that takeoff, `z` up, all in cm.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeofenceConfig {
+    pub enabled: bool,
+    pub area: FenceArea,
+    /// Lowest allowed height once airborne
+    pub floor_cm: f64,
+    pub ceiling_cm: f64,
+}
+
+impl Default for GeofenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            area: FenceArea::Box {
+                x_min_cm: -250.0,
+                x_max_cm: 250.0,
+                y_min_cm: -250.0,
+                y_max_cm: 250.0,
+            },
+            floor_cm: 20.0,
+            ceiling_cm: 200.0,
+        }
+    }
+}
+
+/// Checks against the flight status, see [`crate::flight`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlightConfig {
+    /// Refuse commands that do not fit the flight status
+    pub enforce: bool,
+    /// Battery percentage below which `flip` is refused, as the drone does
+    pub min_flip_battery: usize,
+}
+
+impl Default for FlightConfig {
+    fn default() -> Self {
+        Self {
+            enforce: true,
+            min_flip_battery: 50,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    /// A value that parses but cannot work
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read config: {}", e),
+            Self::Parse(e) => write!(f, "failed to parse config: {}", e),
+            Self::Invalid(e) => write!(f, "invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let s = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        s.parse()
+    }
+
+    /// Rejects values the proxy cannot run with.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.res_timeout_ms == 0 {
+            return Err(ConfigError::Invalid(
+                "res_timeout_ms must be above 0".to_string(),
+            ));
+        }
+        let max_ttl_ms = MAX_SUBSCRIBER_TTL.as_millis() as u64;
+        if self.video.subscriber_ttl_ms > max_ttl_ms {
+            return Err(ConfigError::Invalid(format!(
+                "video.subscriber_ttl_ms must be at most {}",
+                max_ttl_ms
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: Self = toml::from_str(s).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
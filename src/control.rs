@@ -0,0 +1,102 @@
+//! Line-based control ports.
+
+use std::net::SocketAddr;
+
+use log::{error, info};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    select,
+    task::JoinSet,
+};
+
+use crate::recorder::Recorder;
+
+/// Serves a line-based control port: every request line is answered with
+/// the single line returned by `handle`.
+pub async fn listen_control<F>(
+    listener: TcpListener,
+    name: &'static str,
+    handle: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Fn(&str) -> String + Clone + Send + 'static,
+{
+    // multi clients, closed when the listener stops
+    let mut clients = JoinSet::new();
+    loop {
+        let handle = handle.clone();
+
+        info!("listen {}: Waiting connection...", name);
+        let (stream, addr) = match accept_client(&listener, &mut clients).await {
+            Ok(r) => r,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        info!("listen {}: Connected from {}", name, addr);
+
+        clients.spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let res = format!("{}\n", handle(line.trim()));
+                if let Err(e) = writer.write_all(res.as_bytes()).await {
+                    error!(
+                        "listen {}: Failed to send data to client ({}): {:?}",
+                        name, addr, e
+                    );
+                    break;
+                }
+            }
+            info!("listen {}: End of connection with client ({})", name, addr);
+        });
+    }
+}
+
+/// Waits for the next client, reaping the connections that ended meanwhile.
+pub(crate) async fn accept_client(
+    listener: &TcpListener,
+    clients: &mut JoinSet<()>,
+) -> std::io::Result<(TcpStream, SocketAddr)> {
+    loop {
+        select! {
+            res = listener.accept() => return res,
+            Some(_) = clients.join_next() => (),
+        }
+    }
+}
+
+/// Recording control, one request per line: `start`, `stop` and `status`.
+pub async fn listen_record_control(
+    listener: TcpListener,
+    recorder: Recorder,
+) -> Result<(), Box<dyn std::error::Error>> {
+    listen_control(listener, "record control", move |line| {
+        control_record(&recorder, line)
+    })
+    .await
+}
+
+fn control_record(recorder: &Recorder, line: &str) -> String {
+    let res = match line {
+        "start" => recorder.start(),
+        "stop" => recorder.stop(),
+        "status" => {
+            return match recorder.session_dir() {
+                Some(dir) => format!("recording {}", dir.display()),
+                None => "idle".to_string(),
+            }
+        }
+        _ => return format!("error unknown request \"{}\"", line),
+    };
+
+    match res {
+        Ok(dir) => format!("ok {}", dir.display()),
+        Err(e) => format!("error {}", e),
+    }
+}
@@ -0,0 +1,163 @@
+//! Flight status: whether the drone is on the ground or in the air, from the
+//! commands it answered and the height in the states. Commands that do not
+//! fit the status are refused before they reach the drone.
+
+use std::{
+    fmt::{Display, Formatter},
+    sync::{Arc, Mutex},
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{cmd::Command, config::FlightConfig, pose::TOF_OUT_OF_RANGE, state::State};
+
+// `tof` above which the drone is off the ground, whatever `h` says
+const AIRBORNE_TOF: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlightStatus {
+    #[default]
+    Grounded,
+    /// `takeoff` sent, not answered yet
+    TakingOff,
+    Airborne,
+    /// `land` sent, not answered yet
+    Landing,
+}
+
+impl FlightStatus {
+    /// Whether the drone may be off the ground: anything but grounded.
+    pub fn in_air(self) -> bool {
+        self != Self::Grounded
+    }
+}
+
+impl Display for FlightStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Grounded => write!(f, "grounded"),
+            Self::TakingOff => write!(f, "taking off"),
+            Self::Airborne => write!(f, "airborne"),
+            Self::Landing => write!(f, "landing"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Machine {
+    status: FlightStatus,
+    battery: Option<usize>,
+}
+
+impl Machine {
+    fn set(&mut self, status: FlightStatus) {
+        if self.status != status {
+            info!("flight: {} -> {}", self.status, status);
+            self.status = status;
+        }
+    }
+}
+
+/// Shared flight status: fed by the dispatcher with the commands and by the
+/// state relay with the states.
+///
+/// The states only move the drone between grounded and airborne, e.g. when
+/// it lands on its own; taking off and landing end with the answer.
+#[derive(Debug, Clone)]
+pub struct Flight {
+    config: Arc<FlightConfig>,
+    machine: Arc<Mutex<Machine>>,
+}
+
+impl Flight {
+    pub fn new(config: FlightConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            machine: Arc::new(Mutex::new(Machine::default())),
+        }
+    }
+
+    pub fn status(&self) -> FlightStatus {
+        self.machine.lock().unwrap().status
+    }
+
+    /// Refuses takeoff unless grounded, moves unless airborne, and `flip`
+    /// below `min_flip_battery`. Landing is never refused, and neither is
+    /// `rc`, which has no answer to refuse it with.
+    pub fn check(&self, cmd: &Command) -> Result<(), String> {
+        if !self.config.enforce {
+            return Ok(());
+        }
+
+        let machine = self.machine.lock().unwrap();
+        match (cmd, machine.status) {
+            (Command::Takeoff | Command::ThrowFly, FlightStatus::Grounded) => Ok(()),
+            (Command::Takeoff | Command::ThrowFly, status) => {
+                Err(format!("{} while {}", cmd, status))
+            }
+            (Command::Flip(_), FlightStatus::Airborne) => match machine.battery {
+                Some(battery) if battery < self.config.min_flip_battery => Err(format!(
+                    "{} below {}% battery ({}%)",
+                    cmd, self.config.min_flip_battery, battery
+                )),
+                _ => Ok(()),
+            },
+            (_, FlightStatus::Airborne) => Ok(()),
+            (
+                Command::Up(_)
+                | Command::Down(_)
+                | Command::Left(_)
+                | Command::Right(_)
+                | Command::Forward(_)
+                | Command::Back(_)
+                | Command::ClockwiseRotation(_)
+                | Command::CounterClockwiseRotation(_)
+                | Command::Flip(_)
+                | Command::Go { .. }
+                | Command::Curve { .. }
+                | Command::Jump { .. },
+                status,
+            ) => Err(format!("{} while {}", cmd, status)),
+            _ => Ok(()),
+        }
+    }
+
+    /// `cmd` is on its way to the drone.
+    pub fn sent(&self, cmd: &Command) {
+        let status = match cmd {
+            Command::Takeoff | Command::ThrowFly => FlightStatus::TakingOff,
+            Command::Land => FlightStatus::Landing,
+            // the motors stop right away
+            Command::Emergency => FlightStatus::Grounded,
+            _ => return,
+        };
+        self.machine.lock().unwrap().set(status);
+    }
+
+    /// `cmd` was answered, `ok` or not, or `None` if it timed out.
+    pub fn answered(&self, cmd: &Command, ok: Option<bool>) {
+        let status = match (cmd, ok) {
+            (Command::Takeoff | Command::ThrowFly, Some(false)) => FlightStatus::Grounded,
+            (Command::Land, Some(true)) => FlightStatus::Grounded,
+            // when in doubt, the states tell once it settles
+            (Command::Takeoff | Command::ThrowFly | Command::Land, _) => FlightStatus::Airborne,
+            _ => return,
+        };
+        self.machine.lock().unwrap().set(status);
+    }
+
+    pub fn on_state(&self, state: &State) {
+        let airborne =
+            state.height > 0 || (AIRBORNE_TOF..TOF_OUT_OF_RANGE).contains(&state.time_of_flight);
+
+        let mut machine = self.machine.lock().unwrap();
+        machine.battery = Some(state.battery);
+        match (machine.status, airborne) {
+            (FlightStatus::Grounded, true) => machine.set(FlightStatus::Airborne),
+            (FlightStatus::Airborne, false) => machine.set(FlightStatus::Grounded),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Geofence: estimates the position from the commands the drone executed,
+//! and refuses moves whose end point would leave the configured area.
+
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+
+use crate::{cmd::Command, config::GeofenceConfig, flight::Flight, pose::Pose};
+
+/// Height the drone climbs to on takeoff, in cm.
+const TAKEOFF_HEIGHT: f64 = 80.0;
+
+/// The horizontal shape of the fence, around the first takeoff point.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FenceArea {
+    Box {
+        x_min_cm: f64,
+        x_max_cm: f64,
+        y_min_cm: f64,
+        y_max_cm: f64,
+    },
+    Cylinder {
+        radius_cm: f64,
+    },
+}
+
+/// Shared position estimate, checked by the dispatcher before every command
+/// and moved on by the ones the drone answered `ok` to while `flight` has it
+/// in the air.
+///
+/// `rc` and `flip` are not tracked, and a `curve` is checked at its two
+/// points only.
+#[derive(Debug, Clone)]
+pub struct Geofence {
+    config: Arc<GeofenceConfig>,
+    flight: Flight,
+    position: Arc<Mutex<Pose>>,
+}
+
+impl Geofence {
+    pub fn new(config: GeofenceConfig, flight: Flight) -> Self {
+        Self {
+            config: Arc::new(config),
+            flight,
+            position: Arc::new(Mutex::new(Pose::default())),
+        }
+    }
+
+    /// Estimated position from the first takeoff point.
+    pub fn position(&self) -> Pose {
+        *self.position.lock().unwrap()
+    }
+
+    /// Refuses takeoff outside the fence, and moves that would end outside
+    /// it. Moves on the ground are left to the drone to refuse.
+    pub fn check(&self, cmd: &Command) -> Result<(), String> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let in_air = self.flight.status().in_air();
+        let position = self.position.lock().unwrap();
+        let mut points = Vec::new();
+        match cmd {
+            Command::Takeoff | Command::ThrowFly => {}
+            _ if !in_air => return Ok(()),
+            Command::Go { mid: Some(_), .. }
+            | Command::Curve { mid: Some(_), .. }
+            | Command::Jump { .. } => {
+                return Err(format!(
+                    "{} is relative to a mission pad and cannot be checked against the geofence",
+                    cmd
+                ))
+            }
+            // the arc passes through the first point
+            Command::Curve { x1, y1, z1, .. } => {
+                points.push(position.offset(x1.get() as f64, y1.get() as f64, z1.get() as f64))
+            }
+            Command::Up(_)
+            | Command::Down(_)
+            | Command::Left(_)
+            | Command::Right(_)
+            | Command::Forward(_)
+            | Command::Back(_)
+            | Command::Go { .. } => {}
+            _ => return Ok(()),
+        }
+        points.extend(predict(&position, cmd));
+
+        for point in points {
+            if let Some(reason) = self.outside(&point) {
+                return Err(format!(
+                    "{} would leave the geofence: {} at ({:.0}, {:.0}, {:.0})",
+                    cmd, reason, point.x, point.y, point.z
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the estimate on by a command the drone answered `ok` to.
+    ///
+    /// Called before `flight` hears the answer, so taking off and landing
+    /// count as in the air; `emergency` has grounded it already.
+    pub fn executed(&self, cmd: &Command) {
+        let sets_height = matches!(
+            cmd,
+            Command::Takeoff | Command::ThrowFly | Command::Land | Command::Emergency
+        );
+        if !self.flight.status().in_air() && !sets_height {
+            return;
+        }
+
+        let mut position = self.position.lock().unwrap();
+        if let Some(next) = predict(&position, cmd) {
+            *position = next;
+        }
+    }
+
+    fn outside(&self, point: &Pose) -> Option<String> {
+        let config = &self.config;
+        match config.area {
+            FenceArea::Box {
+                x_min_cm,
+                x_max_cm,
+                y_min_cm,
+                y_max_cm,
+            } => {
+                if point.x < x_min_cm || point.x > x_max_cm {
+                    return Some(format!("x outside {} to {}", x_min_cm, x_max_cm));
+                }
+                if point.y < y_min_cm || point.y > y_max_cm {
+                    return Some(format!("y outside {} to {}", y_min_cm, y_max_cm));
+                }
+            }
+            FenceArea::Cylinder { radius_cm } => {
+                if point.x.hypot(point.y) > radius_cm {
+                    return Some(format!("farther than {}cm", radius_cm));
+                }
+            }
+        }
+
+        if point.z > config.ceiling_cm {
+            Some(format!("above the {}cm ceiling", config.ceiling_cm))
+        } else if point.z < config.floor_cm {
+            Some(format!("below the {}cm floor", config.floor_cm))
+        } else {
+            None
+        }
+    }
+}
+
+// where `cmd` ends, if it moves the drone in a known way
+fn predict(position: &Pose, cmd: &Command) -> Option<Pose> {
+    let position = match cmd {
+        Command::Takeoff | Command::ThrowFly => Pose {
+            z: TAKEOFF_HEIGHT,
+            ..*position
+        },
+        Command::Land | Command::Emergency => Pose {
+            z: 0.0,
+            ..*position
+        },
+        Command::Up(d) => position.offset(0.0, 0.0, d.get() as f64),
+        Command::Down(d) => position.offset(0.0, 0.0, -(d.get() as f64)),
+        Command::Left(d) => position.offset(0.0, d.get() as f64, 0.0),
+        Command::Right(d) => position.offset(0.0, -(d.get() as f64), 0.0),
+        Command::Forward(d) => position.offset(d.get() as f64, 0.0, 0.0),
+        Command::Back(d) => position.offset(-(d.get() as f64), 0.0, 0.0),
+        Command::ClockwiseRotation(a) => position.rotate(a.get() as f64),
+        Command::CounterClockwiseRotation(a) => position.rotate(-(a.get() as f64)),
+        Command::Go {
+            x, y, z, mid: None, ..
+        } => position.offset(x.get() as f64, y.get() as f64, z.get() as f64),
+        Command::Curve {
+            x2,
+            y2,
+            z2,
+            mid: None,
+            ..
+        } => position.offset(x2.get() as f64, y2.get() as f64, z2.get() as f64),
+        _ => return None,
+    };
+    Some(position)
+}
@@ -0,0 +1,312 @@
+//! H.264 Annex-B parsing for the Tello video stream.
+//!
+//! The drone sends a raw byte stream cut into 1460-byte UDP chunks with no
+//! regard for NAL unit or frame boundaries. [`AccessUnitAssembler`] puts the
+//! frames back together, and [`StreamCache`] keeps what a decoder joining
+//! mid-stream needs before it can show anything.
+
+/// Size of the video chunks sent by the drone
+pub const VIDEO_PACKET_SIZE: usize = 1460;
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+// a NAL unit larger than this means we are not looking at H.264
+const MAX_NAL_SIZE: usize = 1 << 20;
+// frames cached after a keyframe, 10s at 30fps
+const MAX_CACHED_UNITS: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NalType {
+    /// Coded slice of a non-IDR picture
+    Slice,
+    /// Coded slice of an IDR picture (keyframe)
+    Idr,
+    Sei,
+    Sps,
+    Pps,
+    /// Access unit delimiter
+    Aud,
+    Other(u8),
+}
+
+impl From<u8> for NalType {
+    fn from(header: u8) -> Self {
+        match header & 0x1f {
+            1 => Self::Slice,
+            5 => Self::Idr,
+            6 => Self::Sei,
+            7 => Self::Sps,
+            8 => Self::Pps,
+            9 => Self::Aud,
+            t => Self::Other(t),
+        }
+    }
+}
+
+/// A NAL unit without its start code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NalUnit(Vec<u8>);
+
+impl NalUnit {
+    /// Returns `None` for an empty unit.
+    pub fn new(data: Vec<u8>) -> Option<Self> {
+        if data.is_empty() {
+            None
+        } else {
+            Some(Self(data))
+        }
+    }
+
+    pub fn nal_type(&self) -> NalType {
+        NalType::from(self.0[0])
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn is_vcl(&self) -> bool {
+        matches!(self.nal_type(), NalType::Slice | NalType::Idr)
+    }
+
+    /// Whether this unit begins a new access unit when it follows a slice.
+    /// Slices only do so when they are the first slice of a picture
+    /// (`first_mb_in_slice` is 0, i.e. the first bit of the header is set).
+    fn starts_access_unit(&self) -> bool {
+        match self.nal_type() {
+            NalType::Aud | NalType::Sps | NalType::Pps | NalType::Sei => true,
+            NalType::Slice | NalType::Idr => self.0.get(1).is_some_and(|b| b & 0x80 != 0),
+            NalType::Other(_) => false,
+        }
+    }
+
+    fn write_annex_b(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&START_CODE);
+        out.extend_from_slice(&self.0);
+    }
+}
+
+/// One coded picture together with the parameter sets and SEI sent before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessUnit {
+    pub nal_units: Vec<NalUnit>,
+}
+
+impl AccessUnit {
+    pub fn is_keyframe(&self) -> bool {
+        self.nal_units.iter().any(|n| n.nal_type() == NalType::Idr)
+    }
+
+    /// The unit as an Annex-B byte stream, each NAL unit prefixed with `00 00 00 01`.
+    pub fn to_annex_b(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for nal in &self.nal_units {
+            nal.write_annex_b(&mut out);
+        }
+        out
+    }
+}
+
+/// Splits an Annex-B byte stream into NAL units.
+///
+/// A unit is only complete once the next start code arrives, so the last
+/// unit pushed is held back until then (or until [`NalReader::finish`]).
+#[derive(Debug, Default)]
+pub struct NalReader {
+    buf: Vec<u8>,
+    // offset of the current unit's first byte, `None` before the first start code
+    start: Option<usize>,
+    // where to resume looking for a start code
+    scan: usize,
+}
+
+impl NalReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Vec<NalUnit> {
+        self.buf.extend_from_slice(data);
+        let mut nal_units = Vec::new();
+
+        while let Some(pos) = find_start_code(&self.buf, self.scan) {
+            if let Some(start) = self.start {
+                nal_units.extend(nal_unit(&self.buf[start..pos]));
+            }
+            self.start = Some(pos + 3);
+            self.scan = pos + 3;
+        }
+        // a start code may be split across chunks
+        self.scan = self.buf.len().saturating_sub(2).max(self.scan);
+
+        // drop what has been consumed (or garbage before the first start code)
+        let consumed = self.start.unwrap_or(self.scan);
+        self.buf.drain(..consumed);
+        self.scan -= consumed;
+        self.start = self.start.map(|_| 0);
+
+        if self.buf.len() > MAX_NAL_SIZE {
+            self.buf.clear();
+            self.start = None;
+            self.scan = 0;
+        }
+
+        nal_units
+    }
+
+    /// The first bytes of the unit held back, enough to tell its type and
+    /// whether it starts an access unit.
+    fn pending_header(&self) -> Option<NalUnit> {
+        let start = self.start?;
+        self.buf
+            .get(start..start + 2)
+            .and_then(|h| NalUnit::new(h.to_vec()))
+    }
+
+    /// Returns the unit still held back, at the end of the stream.
+    pub fn finish(&mut self) -> Option<NalUnit> {
+        let nal = self.start.and_then(|start| nal_unit(&self.buf[start..]));
+        *self = Self::default();
+        nal
+    }
+}
+
+fn find_start_code(buf: &[u8], from: usize) -> Option<usize> {
+    buf.get(from..)?
+        .windows(3)
+        .position(|w| w == [0, 0, 1])
+        .map(|i| from + i)
+}
+
+fn nal_unit(data: &[u8]) -> Option<NalUnit> {
+    // trailing zeros belong to the next (4-byte) start code
+    let end = data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    NalUnit::new(data[..end].to_vec())
+}
+
+/// Rebuilds access units (frames) from the chunks received from the drone.
+#[derive(Debug, Default)]
+pub struct AccessUnitAssembler {
+    reader: NalReader,
+    nal_units: Vec<NalUnit>,
+    has_vcl: bool,
+}
+
+impl AccessUnitAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the access units completed by `chunk`.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<AccessUnit> {
+        let nal_units = self.reader.push(chunk);
+        let mut access_units: Vec<_> = nal_units
+            .into_iter()
+            .filter_map(|nal| self.push_nal_unit(nal))
+            .collect();
+
+        // don't wait for the next unit to complete when its header already
+        // tells that the current picture is done
+        if self.has_vcl
+            && self
+                .reader
+                .pending_header()
+                .is_some_and(|header| header.starts_access_unit())
+        {
+            access_units.extend(self.take());
+        }
+
+        access_units
+    }
+
+    /// Returns the access units still held back, at the end of the stream.
+    pub fn finish(&mut self) -> Vec<AccessUnit> {
+        let mut access_units = Vec::new();
+        if let Some(nal) = self.reader.finish() {
+            access_units.extend(self.push_nal_unit(nal));
+        }
+        access_units.extend(self.take());
+        access_units
+    }
+
+    fn push_nal_unit(&mut self, nal: NalUnit) -> Option<AccessUnit> {
+        let done = if self.has_vcl && nal.starts_access_unit() {
+            self.take()
+        } else {
+            None
+        };
+
+        self.has_vcl |= nal.is_vcl();
+        self.nal_units.push(nal);
+        done
+    }
+
+    fn take(&mut self) -> Option<AccessUnit> {
+        self.has_vcl = false;
+        if self.nal_units.is_empty() {
+            None
+        } else {
+            Some(AccessUnit {
+                nal_units: std::mem::take(&mut self.nal_units),
+            })
+        }
+    }
+}
+
+/// The latest parameter sets, keyframe and the frames since that keyframe.
+#[derive(Debug, Clone, Default)]
+pub struct StreamCache {
+    sps: Option<NalUnit>,
+    pps: Option<NalUnit>,
+    // the latest keyframe first
+    since_keyframe: Vec<AccessUnit>,
+}
+
+impl StreamCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, access_unit: &AccessUnit) {
+        for nal in &access_unit.nal_units {
+            match nal.nal_type() {
+                NalType::Sps => self.sps = Some(nal.clone()),
+                NalType::Pps => self.pps = Some(nal.clone()),
+                _ => (),
+            }
+        }
+
+        if access_unit.is_keyframe() {
+            self.since_keyframe.clear();
+        } else if self.since_keyframe.is_empty() {
+            return;
+        } else if self.since_keyframe.len() >= MAX_CACHED_UNITS {
+            // too long without a keyframe, new consumers wait for the next one
+            self.since_keyframe.clear();
+            return;
+        }
+        self.since_keyframe.push(access_unit.clone());
+    }
+
+    /// SPS, PPS, the latest keyframe and every frame since as an Annex-B
+    /// byte stream, or `None` until all three have been seen. Sending this
+    /// first lets a decoder that joins mid-stream start right away, without
+    /// missing the frames the next one refers to. Call after
+    /// [`StreamCache::update`] with the latest unit, which it ends with.
+    pub fn primer(&self) -> Option<Vec<u8>> {
+        let (sps, pps) = (self.sps.as_ref()?, self.pps.as_ref()?);
+        if self.since_keyframe.is_empty() {
+            return None;
+        }
+
+        let mut out = Vec::new();
+        sps.write_annex_b(&mut out);
+        pps.write_annex_b(&mut out);
+        for nal in self.since_keyframe.iter().flat_map(|au| &au.nal_units) {
+            if !matches!(nal.nal_type(), NalType::Sps | NalType::Pps) {
+                nal.write_annex_b(&mut out);
+            }
+        }
+        Some(out)
+    }
+}
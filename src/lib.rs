@@ -1,2 +1,20 @@
 pub mod state;
 pub mod cmd;
+pub mod config;
+pub mod cli;
+pub mod h264;
+pub mod recorder;
+pub mod replay;
+pub mod sim;
+pub mod control;
+pub mod proxy;
+pub mod relay;
+pub mod video;
+pub mod tello_proxy;
+pub mod client;
+pub mod safety;
+pub mod geofence;
+pub mod pose;
+pub mod flight;
+
+pub use tello_proxy::{TelloProxy, TelloProxyBuilder};
@@ -0,0 +1,159 @@
+//! Dead reckoning: integrates the speeds of the states into a position.
+//!
+//! The speeds are taken in the body frame (x forward, y left, z up, in dm/s
+//! like the drone sends them) and turned into the takeoff frame with `yaw`.
+//! The height is pulled towards `tof`, or `h` when the ground is out of the
+//! sensor's range, so it does not drift.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{flight::FlightStatus, state::State};
+
+// `tof` when the ground is out of range
+pub(crate) const TOF_OUT_OF_RANGE: usize = 6553;
+// `tof` on the ground, until one is seen
+const GROUND_TOF: usize = 10;
+// share of the height error corrected per state
+const HEIGHT_CORRECTION: f64 = 0.5;
+// longer gaps between states are not integrated over
+const MAX_STEP_MS: u64 = 1000;
+
+/// Position in cm from a takeoff point: `x` forward and `y` left at that
+/// takeoff, `z` up.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Pose {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    /// Heading in degrees, clockwise from that takeoff, -180 to 180
+    pub yaw: f64,
+}
+
+impl Pose {
+    /// Moved by `x` forward, `y` left and `z` up from the current heading.
+    pub fn offset(&self, x: f64, y: f64, z: f64) -> Self {
+        let (sin, cos) = self.yaw.to_radians().sin_cos();
+        Self {
+            x: self.x + x * cos + y * sin,
+            y: self.y - x * sin + y * cos,
+            z: self.z + z,
+            yaw: self.yaw,
+        }
+    }
+
+    /// Turned clockwise by `angle` degrees.
+    pub fn rotate(&self, angle: f64) -> Self {
+        Self {
+            yaw: wrap_yaw(self.yaw + angle),
+            ..*self
+        }
+    }
+}
+
+/// Turns the states, in order, into poses. Reset on every takeoff.
+#[derive(Debug)]
+pub struct PoseEstimator {
+    pose: Pose,
+    airborne: bool,
+    // the yaw the heading is measured from
+    origin_yaw: f64,
+    // the last readings on the ground, the origin of the next takeoff
+    ground_yaw: Option<f64>,
+    ground_tof: usize,
+    // time and takeoff frame velocity in cm/s of the previous state
+    last: Option<(u64, [f64; 3])>,
+}
+
+impl Default for PoseEstimator {
+    fn default() -> Self {
+        Self {
+            pose: Pose::default(),
+            airborne: false,
+            origin_yaw: 0.0,
+            ground_yaw: None,
+            ground_tof: GROUND_TOF,
+            last: None,
+        }
+    }
+}
+
+impl PoseEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    /// Moves the pose on to `state`, received at `timestamp_ms` while the
+    /// flight status was `flight`.
+    pub fn update(&mut self, state: &State, flight: FlightStatus, timestamp_ms: u64) -> Pose {
+        let yaw = state.yaw as f64;
+        let airborne = flight.in_air();
+
+        if !airborne {
+            self.ground_yaw = Some(yaw);
+            self.ground_tof = state.time_of_flight;
+        }
+        if self.last.is_none() {
+            self.origin_yaw = yaw;
+        }
+        if airborne && !self.airborne {
+            // takeoff, or from wherever the drone is when first seen in the air
+            self.origin_yaw = self.ground_yaw.unwrap_or(yaw);
+            self.pose = Pose {
+                z: self.measured_height(state),
+                ..Pose::default()
+            };
+        }
+        self.airborne = airborne;
+
+        let heading = wrap_yaw(yaw - self.origin_yaw);
+        // the body speeds as a 1s move from the origin, facing `heading`
+        let moved = Pose {
+            yaw: heading,
+            ..Pose::default()
+        }
+        .offset(
+            state.speeds.x as f64 * 10.0,
+            state.speeds.y as f64 * 10.0,
+            state.speeds.z as f64 * 10.0,
+        );
+        let velocity = [moved.x, moved.y, moved.z];
+
+        if let Some((last_ms, last_velocity)) = self.last {
+            let step_ms = timestamp_ms.saturating_sub(last_ms);
+            if step_ms <= MAX_STEP_MS {
+                let dt = step_ms as f64 / 1000.0;
+                self.pose.x += (last_velocity[0] + velocity[0]) / 2.0 * dt;
+                self.pose.y += (last_velocity[1] + velocity[1]) / 2.0 * dt;
+                self.pose.z += (last_velocity[2] + velocity[2]) / 2.0 * dt;
+            }
+        }
+        self.last = Some((timestamp_ms, velocity));
+
+        if airborne {
+            let error = self.measured_height(state) - self.pose.z;
+            self.pose.z += HEIGHT_CORRECTION * error;
+        } else {
+            self.pose.z = 0.0;
+        }
+        self.pose.yaw = heading;
+
+        self.pose
+    }
+
+    fn measured_height(&self, state: &State) -> f64 {
+        if state.time_of_flight < TOF_OUT_OF_RANGE {
+            state.time_of_flight.saturating_sub(self.ground_tof) as f64
+        } else {
+            state.height as f64
+        }
+    }
+}
+
+/// `angle` in degrees, to -180 ~ 180.
+pub fn wrap_yaw(angle: f64) -> f64 {
+    (angle + 180.0).rem_euclid(360.0) - 180.0
+}
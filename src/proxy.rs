@@ -0,0 +1,589 @@
+//! Command proxy: every client's commands go through one dispatcher that
+//! owns the drone command socket, so responses reach the right client.
+
+use std::{
+    fmt::{Display, Formatter},
+    net::SocketAddr,
+};
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    select,
+    sync::{mpsc, oneshot},
+    task::JoinSet,
+    time::{sleep, sleep_until, timeout, Duration, Instant},
+};
+
+use crate::{
+    cmd::{Command, CommandResult},
+    control::accept_client,
+    flight::Flight,
+    geofence::Geofence,
+    recorder::{CommandRecord, Recorder},
+    safety::Safety,
+    state::timestamp_ms,
+};
+
+/// Sent when idle: a query, so it changes nothing on the drone.
+const KEEPALIVE_CMD: Command = Command::ReadBattery;
+
+/// A command queued for the drone, with the channel its response is routed
+/// back through.
+pub struct CmdRequest {
+    cmd: Command,
+    res_tx: oneshot::Sender<Result<Option<String>, DispatchError>>,
+}
+
+/// What every command has to pass before it is sent to the drone, and
+/// what keeps track of what it did.
+#[derive(Debug, Clone)]
+pub struct CmdGuards {
+    pub safety: Safety,
+    pub flight: Flight,
+    pub geofence: Geofence,
+}
+
+impl CmdGuards {
+    fn check(&self, cmd: &Command) -> Result<(), String> {
+        self.safety.check(cmd)?;
+        self.flight.check(cmd)?;
+        self.geofence.check(cmd)
+    }
+
+    fn sent(&self, cmd: &Command) {
+        self.flight.sent(cmd);
+    }
+
+    // `None` if `cmd` timed out
+    fn answered(&self, cmd: &Command, result: Option<&CommandResult>) {
+        let ok = result.map(|result| *result == CommandResult::Ok);
+        // with the status the command was sent in
+        if ok == Some(true) {
+            self.geofence.executed(cmd);
+        }
+        self.flight.answered(cmd, ok);
+    }
+}
+
+/// Why a command failed when the drone did not answer within `res_timeout`;
+/// the newline-framed command port answers `error ` followed by it.
+pub const TIMEOUT_REASON: &str = "timed out waiting response";
+
+#[derive(Debug)]
+pub enum DispatchError {
+    Send(std::io::Error),
+    Receive(std::io::Error),
+    Timeout,
+    /// Kept from the drone by a failsafe, the flight status or the geofence
+    Refused(String),
+    Closed,
+}
+
+impl Display for DispatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Send(e) => write!(f, "failed to send command to drone: {}", e),
+            Self::Receive(e) => write!(f, "failed to receive response from drone: {}", e),
+            Self::Timeout => write!(f, "{}", TIMEOUT_REASON),
+            Self::Refused(reason) => write!(f, "refused: {}", reason),
+            Self::Closed => write!(f, "command dispatcher stopped"),
+        }
+    }
+}
+
+/// Owns the drone command socket. Commands are sent one at a time in queue
+/// order and each response is routed back to the requester; responses that
+/// arrive after their command timed out are dropped.
+///
+/// Once a command has been sent, `battery?` is sent whenever nothing else was
+/// for `keepalive`, so the drone does not land on its own. Commands refused
+/// by `guards` never reach the drone.
+pub async fn dispatch_cmd(
+    dst_socket: UdpSocket,
+    dst_target: SocketAddr,
+    res_timeout: Duration,
+    keepalive: Option<Duration>,
+    mut req_rx: mpsc::Receiver<CmdRequest>,
+    recorder: Recorder,
+    guards: CmdGuards,
+) {
+    let mut buf = vec![0; 1024];
+    let mut last_sent: Option<Instant> = None;
+
+    loop {
+        let req = match (keepalive, last_sent) {
+            (Some(keepalive), Some(sent_at)) => select! {
+                req = req_rx.recv() => req,
+                _ = sleep_until(sent_at + keepalive) => {
+                    send_keepalive(&dst_socket, dst_target, res_timeout, &mut buf).await;
+                    last_sent = Some(Instant::now());
+                    continue;
+                }
+            },
+            _ => req_rx.recv().await,
+        };
+        let req = match req {
+            Some(req) => req,
+            None => break,
+        };
+
+        drop_stale_responses(&dst_socket, &mut buf);
+
+        let sent_at = Instant::now();
+        let mut record = CommandRecord {
+            timestamp_ms: timestamp_ms(),
+            cmd: req.cmd.clone(),
+            response: None,
+            error: None,
+            latency_ms: 0,
+        };
+
+        if let Err(reason) = guards.check(&req.cmd) {
+            let e = DispatchError::Refused(reason);
+            error!("listen cmd: {} {}", req.cmd, e);
+            record.error = Some(e.to_string());
+            recorder.record_command(&record);
+            let _ = req.res_tx.send(Err(e));
+            continue;
+        }
+
+        if let Err(e) = dst_socket
+            .send_to(req.cmd.to_string().as_bytes(), dst_target)
+            .await
+        {
+            error!("listen cmd: Failed to send cmd to target: {:?}", e);
+            let e = DispatchError::Send(e);
+            record.error = Some(e.to_string());
+            recorder.record_command(&record);
+            let _ = req.res_tx.send(Err(e));
+            continue;
+        }
+        last_sent = Some(sent_at);
+        guards.sent(&req.cmd);
+
+        // rc command
+        if let Command::Rc { .. } = req.cmd {
+            recorder.record_command(&record);
+            // wait 0.5s
+            sleep_ms(500).await;
+            let _ = req.res_tx.send(Ok(None));
+            continue;
+        }
+
+        // wait response
+        let res = match timeout(res_timeout, dst_socket.recv_from(&mut buf)).await {
+            Ok(Ok((size, _))) => {
+                let s = String::from_utf8_lossy(&buf[..size]).to_string();
+                let result = CommandResult::from_response(&req.cmd, &s);
+                info!("listen cmd: Receive response from target: {:?}", result);
+                guards.answered(&req.cmd, Some(&result));
+                Ok(Some(s))
+            }
+            Ok(Err(e)) => {
+                error!(
+                    "listen cmd: Failed to receive response from target: {:?}",
+                    e
+                );
+                guards.answered(&req.cmd, None);
+                Err(DispatchError::Receive(e))
+            }
+            Err(_) => {
+                error!("listen cmd: Timed out waiting response");
+                guards.answered(&req.cmd, None);
+                Err(DispatchError::Timeout)
+            }
+        };
+
+        record.latency_ms = sent_at.elapsed().as_millis() as u64;
+        match &res {
+            Ok(response) => record.response = response.as_ref().map(|s| s.trim().to_string()),
+            Err(e) => record.error = Some(e.to_string()),
+        }
+        recorder.record_command(&record);
+
+        // the requester may have gone away
+        let _ = req.res_tx.send(res);
+    }
+}
+
+fn drop_stale_responses(socket: &UdpSocket, buf: &mut [u8]) {
+    while let Ok((size, _)) = socket.try_recv_from(buf) {
+        info!(
+            "listen cmd: Drop stale response from target: {:?}",
+            String::from_utf8_lossy(&buf[..size])
+        );
+    }
+}
+
+// the response is read here, so it never reaches a client
+async fn send_keepalive(
+    socket: &UdpSocket,
+    target: SocketAddr,
+    res_timeout: Duration,
+    buf: &mut [u8],
+) {
+    drop_stale_responses(socket, buf);
+
+    if let Err(e) = socket
+        .send_to(KEEPALIVE_CMD.to_string().as_bytes(), target)
+        .await
+    {
+        error!("keepalive: Failed to send cmd to target: {:?}", e);
+        return;
+    }
+
+    match timeout(res_timeout, socket.recv_from(buf)).await {
+        Ok(Ok((size, _))) => debug!(
+            "keepalive: Receive response from target: {:?}",
+            CommandResult::from_response(&KEEPALIVE_CMD, &String::from_utf8_lossy(&buf[..size]))
+        ),
+        Ok(Err(e)) => error!("keepalive: Failed to receive response from target: {:?}", e),
+        Err(_) => warn!("keepalive: Timed out waiting response"),
+    }
+}
+
+/// How commands and responses are delimited on a command port.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// One command per line, every response is terminated with `\n`.
+    Line,
+    /// Commands separated by `A`, responses written without a delimiter and
+    /// failures answered with a bare `error`, as clients written against the
+    /// original protocol expect.
+    Legacy,
+}
+
+pub async fn listen_and_send_cmd(
+    listener: TcpListener,
+    framing: Framing,
+    req_tx: mpsc::Sender<CmdRequest>,
+    safety: Safety,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // multi clients, closed when the listener stops
+    let mut clients = JoinSet::new();
+    loop {
+        let req_tx = req_tx.clone();
+
+        info!("listen cmd: Waiting connection...");
+        let (stream, addr) = match accept_client(&listener, &mut clients).await {
+            Ok(r) => r,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        info!("listen cmd: Connected from {} ({:?})", addr, framing);
+        let link = safety.link();
+
+        clients.spawn(async move {
+            let _link = link;
+            match framing {
+                Framing::Line => serve_line_client(stream, addr, req_tx).await,
+                Framing::Legacy => serve_legacy_client(stream, addr, req_tx).await,
+            }
+            info!("listen cmd: End of connection with client ({})", addr);
+        });
+    }
+}
+
+async fn serve_line_client(stream: TcpStream, addr: SocketAddr, req_tx: mpsc::Sender<CmdRequest>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                error!(
+                    "listen cmd: Error while reading from client ({}): {:?}",
+                    addr, e
+                );
+                break;
+            }
+        };
+
+        let cmd_str = line.trim();
+        if cmd_str.is_empty() {
+            continue;
+        }
+
+        let res = match request_cmd(&req_tx, addr, cmd_str).await {
+            Ok(Some(res)) => res,
+            Ok(None) => continue,
+            Err(reason) => format!("error {}", reason),
+        };
+
+        let res = format!("{}\n", res.trim_end());
+        if let Err(e) = writer.write_all(res.as_bytes()).await {
+            error!(
+                "listen cmd: Failed to send data to client ({}): {:?}",
+                addr, e
+            );
+            break;
+        }
+    }
+}
+
+async fn serve_legacy_client(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    req_tx: mpsc::Sender<CmdRequest>,
+) {
+    let mut buf = vec![0; 1024];
+
+    loop {
+        let size = match stream.read(&mut buf).await {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(e) => {
+                error!(
+                    "listen cmd: Error while reading from client ({}): {:?}",
+                    addr, e
+                );
+                break;
+            }
+        };
+
+        let s = String::from_utf8_lossy(&buf[..size]).replace(['\n', '\r'], "");
+
+        for cmd_str in s.split('A') {
+            if cmd_str.is_empty() {
+                continue;
+            }
+
+            let res = match request_cmd(&req_tx, addr, cmd_str).await {
+                Ok(Some(res)) => res,
+                Ok(None) => continue,
+                Err(_) => "error".to_string(),
+            };
+
+            if let Err(e) = stream.write_all(res.as_bytes()).await {
+                error!(
+                    "listen cmd: Failed to send data to client ({}): {:?}",
+                    addr, e
+                );
+            }
+        }
+    }
+}
+
+/// Parses `cmd_str` and queues it to the drone. Returns the drone's response,
+/// `None` if the command has no response (`rc`), or why it failed.
+async fn request_cmd<C: Display>(
+    req_tx: &mpsc::Sender<CmdRequest>,
+    client: C,
+    cmd_str: &str,
+) -> Result<Option<String>, String> {
+    let cmd = match cmd_str.parse::<Command>() {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            error!("Invalid command: \"{}\" ({})", cmd_str, e);
+            return Err(e.to_string());
+        }
+    };
+
+    info!(
+        "listen cmd: Receive command from client ({}): {:?}",
+        client, cmd
+    );
+
+    dispatch(req_tx, cmd).await.map_err(|e| e.to_string())
+}
+
+/// Queues `cmd` to the dispatcher and waits for the drone's raw response.
+pub async fn dispatch(
+    req_tx: &mpsc::Sender<CmdRequest>,
+    cmd: Command,
+) -> Result<Option<String>, DispatchError> {
+    let (res_tx, res_rx) = oneshot::channel();
+
+    if req_tx.send(CmdRequest { cmd, res_tx }).await.is_err() {
+        return Err(DispatchError::Closed);
+    }
+
+    res_rx.await.unwrap_or(Err(DispatchError::Closed))
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    cmd: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcResponse {
+    id: Value,
+    #[serde(flatten)]
+    outcome: RpcOutcome,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum RpcOutcome {
+    /// `result` is null for commands without a response (`rc`)
+    Result {
+        result: Option<CommandResult>,
+        latency_ms: u64,
+    },
+    Error {
+        error: String,
+    },
+}
+
+/// JSON control API: one request object per line, e.g.
+/// `{"id":1,"cmd":{"forward":50}}` -> `{"id":1,"result":"ok","latency_ms":120}`.
+pub async fn listen_and_send_rpc(
+    listener: TcpListener,
+    req_tx: mpsc::Sender<CmdRequest>,
+    safety: Safety,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // multi clients, closed when the listener stops
+    let mut clients = JoinSet::new();
+    loop {
+        let req_tx = req_tx.clone();
+
+        info!("listen rpc: Waiting connection...");
+        let (stream, addr) = match accept_client(&listener, &mut clients).await {
+            Ok(r) => r,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        info!("listen rpc: Connected from {}", addr);
+        let link = safety.link();
+
+        clients.spawn(async move {
+            let _link = link;
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            loop {
+                let line = match lines.next_line().await {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!(
+                            "listen rpc: Error while reading from client ({}): {:?}",
+                            addr, e
+                        );
+                        break;
+                    }
+                };
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let res = request_rpc(&req_tx, addr, &line).await;
+                let json = format!("{}\n", serde_json::to_string(&res).unwrap());
+
+                if let Err(e) = writer.write_all(json.as_bytes()).await {
+                    error!(
+                        "listen rpc: Failed to send data to client ({}): {:?}",
+                        addr, e
+                    );
+                    break;
+                }
+            }
+            info!("listen rpc: End of connection with client ({})", addr);
+        });
+    }
+}
+
+async fn request_rpc(
+    req_tx: &mpsc::Sender<CmdRequest>,
+    addr: SocketAddr,
+    line: &str,
+) -> RpcResponse {
+    let req: RpcRequest = match serde_json::from_str(line) {
+        Ok(req) => req,
+        Err(e) => {
+            return RpcResponse {
+                id: Value::Null,
+                outcome: RpcOutcome::Error {
+                    error: format!("invalid request: {}", e),
+                },
+            }
+        }
+    };
+
+    let cmd: Command = match serde_json::from_value(req.cmd) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            return RpcResponse {
+                id: req.id,
+                outcome: RpcOutcome::Error {
+                    error: format!("invalid command: {}", e),
+                },
+            }
+        }
+    };
+
+    info!(
+        "listen rpc: Receive command from client ({}): {:?}",
+        addr, cmd
+    );
+
+    let started_at = Instant::now();
+    let outcome = match dispatch(req_tx, cmd.clone()).await {
+        Ok(res) => RpcOutcome::Result {
+            result: res.map(|s| CommandResult::from_response(&cmd, &s)),
+            latency_ms: started_at.elapsed().as_millis() as u64,
+        },
+        Err(e) => RpcOutcome::Error {
+            error: e.to_string(),
+        },
+    };
+
+    RpcResponse {
+        id: req.id,
+        outcome,
+    }
+}
+
+/// Sends the commands typed on stdin, one per line. They go to the
+/// dispatcher directly, so the console does not count as a command client
+/// for the link-lost failsafe.
+pub async fn listen_stdin(
+    req_tx: mpsc::Sender<CmdRequest>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stdin = async_std::io::stdin();
+    let mut line = String::new();
+
+    loop {
+        match stdin.read_line(&mut line).await {
+            Ok(0) => return Ok(()),
+            Ok(_) => (),
+            Err(_) => continue,
+        }
+
+        let cmd_str = line.trim();
+        if !cmd_str.is_empty() {
+            match request_cmd(&req_tx, "stdin", cmd_str).await {
+                Ok(Some(res)) => info!("listen stdin: {}", res.trim_end()),
+                Ok(None) => (),
+                Err(reason) => error!("listen stdin: error {}", reason),
+            }
+        }
+        line.clear();
+    }
+}
+
+pub async fn shoot_cmd<A: ToSocketAddrs>(
+    target: A,
+    cmd: &Command,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stream = BufReader::new(TcpStream::connect(target).await?);
+    let mut res = String::new();
+    stream.write_all(format!("{}\n", cmd).as_bytes()).await?;
+    stream.read_line(&mut res).await?;
+
+    Ok(())
+}
+
+async fn sleep_ms(ms: u64) {
+    sleep(Duration::from_millis(ms)).await;
+}
@@ -0,0 +1,220 @@
+//! Flight recordings.
+//!
+//! A session is one directory holding the relayed video, the parsed states
+//! and the command log. Every record is stamped with
+//! [`timestamp_ms`](crate::state::timestamp_ms), so the three files can be
+//! lined up afterwards.
+
+use std::{
+    fmt::{Display, Formatter},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cmd::Command,
+    h264::{AccessUnit, StreamCache},
+    state::{timestamp_ms, StateFrame},
+};
+
+/// H.264 Annex-B elementary stream
+pub const VIDEO_FILE: &str = "video.h264";
+/// One [`VideoIndexEntry`] per access unit in [`VIDEO_FILE`]
+pub const VIDEO_INDEX_FILE: &str = "video.ndjson";
+/// One [`StateFrame`] per line
+pub const STATE_FILE: &str = "state.ndjson";
+/// One [`CommandRecord`] per line
+pub const COMMAND_FILE: &str = "commands.ndjson";
+
+/// Where an access unit is in the video file, and when it was received.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoIndexEntry {
+    pub timestamp_ms: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub keyframe: bool,
+}
+
+/// A command sent to the drone and what came back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandRecord {
+    /// When the command was sent
+    pub timestamp_ms: u64,
+    pub cmd: Command,
+    /// Raw response, `None` for commands without one (`rc`) or on error
+    pub response: Option<String>,
+    pub error: Option<String>,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug)]
+pub enum RecorderError {
+    AlreadyRecording(PathBuf),
+    NotRecording,
+    Io(io::Error),
+}
+
+impl Display for RecorderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyRecording(dir) => write!(f, "already recording to {}", dir.display()),
+            Self::NotRecording => write!(f, "not recording"),
+            Self::Io(e) => write!(f, "failed to write recording: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RecorderError {}
+
+#[derive(Debug)]
+struct Session {
+    dir: PathBuf,
+    video: BufWriter<File>,
+    video_offset: u64,
+    // nothing is written before the first keyframe
+    video_started: bool,
+    video_index: BufWriter<File>,
+    state: BufWriter<File>,
+    commands: BufWriter<File>,
+}
+
+impl Session {
+    fn create(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let create = |name| File::create(dir.join(name)).map(BufWriter::new);
+
+        Ok(Self {
+            video: create(VIDEO_FILE)?,
+            video_offset: 0,
+            video_started: false,
+            video_index: create(VIDEO_INDEX_FILE)?,
+            state: create(STATE_FILE)?,
+            commands: create(COMMAND_FILE)?,
+            dir,
+        })
+    }
+
+    fn write_video(&mut self, data: &[u8], keyframe: bool) -> io::Result<()> {
+        self.video.write_all(data)?;
+        write_line(
+            &mut self.video_index,
+            &VideoIndexEntry {
+                timestamp_ms: timestamp_ms(),
+                offset: self.video_offset,
+                size: data.len() as u64,
+                keyframe,
+            },
+        )?;
+        self.video_offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.video.flush()?;
+        self.video_index.flush()?;
+        self.state.flush()?;
+        self.commands.flush()
+    }
+}
+
+fn write_line<W: Write, T: Serialize>(writer: &mut W, value: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")
+}
+
+/// Shared handle to the current recording session. Clones refer to the same
+/// session; the `record_*` methods do nothing while not recording.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    dir: PathBuf,
+    session: Arc<Mutex<Option<Session>>>,
+}
+
+impl Recorder {
+    /// Sessions are created as subdirectories of `dir`.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            session: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Starts a new session and returns its directory.
+    pub fn start(&self) -> Result<PathBuf, RecorderError> {
+        let mut session = self.session.lock().unwrap();
+        if let Some(s) = session.as_ref() {
+            return Err(RecorderError::AlreadyRecording(s.dir.clone()));
+        }
+
+        let dir = self.dir.join(format!("flight-{}", timestamp_ms()));
+        *session = Some(Session::create(dir.clone()).map_err(RecorderError::Io)?);
+        info!("recorder: Start recording to {}", dir.display());
+
+        Ok(dir)
+    }
+
+    /// Flushes and closes the session, returning its directory.
+    pub fn stop(&self) -> Result<PathBuf, RecorderError> {
+        let mut session = self
+            .session
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or(RecorderError::NotRecording)?;
+        session.flush().map_err(RecorderError::Io)?;
+        info!("recorder: Stop recording to {}", session.dir.display());
+
+        Ok(session.dir)
+    }
+
+    /// Directory of the current session.
+    pub fn session_dir(&self) -> Option<PathBuf> {
+        self.session.lock().unwrap().as_ref().map(|s| s.dir.clone())
+    }
+
+    /// Appends a video frame. `cache` must already be updated with it, so a
+    /// session started mid-stream can begin with the parameter sets, the
+    /// latest keyframe and the frames since.
+    pub fn record_video(&self, access_unit: &AccessUnit, cache: &StreamCache) {
+        self.with_session(|s| {
+            if s.video_started {
+                s.write_video(&access_unit.to_annex_b(), access_unit.is_keyframe())
+            } else if let Some(data) = cache.primer() {
+                s.video_started = true;
+                s.write_video(&data, true)
+            } else {
+                Ok(())
+            }
+        });
+    }
+
+    pub fn record_state(&self, frame: &StateFrame) {
+        self.with_session(|s| write_line(&mut s.state, frame));
+    }
+
+    pub fn record_command(&self, record: &CommandRecord) {
+        self.with_session(|s| write_line(&mut s.commands, record));
+    }
+
+    // a session that fails to write is dropped, rather than failing every record after it
+    fn with_session<F: FnOnce(&mut Session) -> io::Result<()>>(&self, f: F) {
+        let mut session = self.session.lock().unwrap();
+        let Some(s) = session.as_mut() else {
+            return;
+        };
+
+        if let Err(e) = f(s) {
+            error!(
+                "recorder: Failed to write to {}, stop recording: {:?}",
+                s.dir.display(),
+                e
+            );
+            *session = None;
+        }
+    }
+}
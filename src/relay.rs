@@ -0,0 +1,115 @@
+//! State relay: states are read from the drone once and published to every
+//! client of the state port as NDJSON.
+
+use std::net::SocketAddr;
+
+use log::{error, info, warn};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, UdpSocket},
+    sync::broadcast,
+    task::JoinSet,
+    time::{timeout, Duration},
+};
+
+use crate::{
+    control::accept_client,
+    flight::Flight,
+    pose::PoseEstimator,
+    recorder::Recorder,
+    state::{State, StateFrame},
+};
+
+// states buffered per client before it starts skipping (~3s at 10Hz)
+pub const STATE_CHANNEL_CAPACITY: usize = 32;
+
+/// Reads states from the drone, parses each one once, feeds it to `flight`,
+/// estimates the pose and publishes them to every subscriber of `state_tx`.
+pub async fn recv_state(
+    src_socket: UdpSocket,
+    doorbell_target: SocketAddr,
+    res_timeout: Duration,
+    state_tx: broadcast::Sender<StateFrame>,
+    recorder: Recorder,
+    flight: Flight,
+) -> Result<(), Box<dyn std::error::Error>> {
+    src_socket.send_to(b"", doorbell_target).await?;
+
+    let mut buf = vec![0; 1024];
+    let mut seq = 0;
+    let mut estimator = PoseEstimator::new();
+
+    loop {
+        let size = match timeout(res_timeout, src_socket.recv_from(&mut buf)).await {
+            Ok(Ok((size, _))) => size,
+            Ok(Err(e)) => {
+                error!("listen state: Failed to receive data from target {:?}", e);
+                continue;
+            }
+            Err(_) => {
+                error!("listen state: Timed out waiting receive data");
+                continue;
+            }
+        };
+
+        let s = String::from_utf8_lossy(&buf[..size]);
+        let state = match State::from_str(&s) {
+            Some(s) => s,
+            None => continue,
+        };
+
+        //info!("listen state: Receive state from target: {:?}", state);
+        flight.on_state(&state);
+        let mut frame = StateFrame::new(seq, state);
+        frame.flight = flight.status();
+        frame.pose = estimator.update(&frame.state, frame.flight, frame.timestamp_ms);
+        recorder.record_state(&frame);
+        // no subscribers is not an error
+        let _ = state_tx.send(frame);
+        seq += 1;
+    }
+}
+
+pub async fn listen_and_send_state(
+    listener: TcpListener,
+    state_tx: broadcast::Sender<StateFrame>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // multi clients, closed when the listener stops
+    let mut clients = JoinSet::new();
+    loop {
+        info!("listen state: Waiting connection...");
+        let (mut stream, addr) = match accept_client(&listener, &mut clients).await {
+            Ok(r) => r,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let mut state_rx = state_tx.subscribe();
+        info!("listen state: Connected from {}", addr);
+
+        clients.spawn(async move {
+            loop {
+                let frame = match state_rx.recv().await {
+                    Ok(frame) => frame,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!(
+                            "listen state: Client ({}) is too slow, skipped {} states",
+                            addr, n
+                        );
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                let json = format!("{}\n", serde_json::to_string(&frame).unwrap());
+                if let Err(e) = stream.write_all(json.as_bytes()).await {
+                    error!(
+                        "listen state: Failed to send data to client ({}): {:?}",
+                        addr, e
+                    );
+                    break;
+                }
+            }
+            info!("listen state: End of connection with client ({})", addr);
+        });
+    }
+}
@@ -0,0 +1,284 @@
+//! Replays captured flights as a fake drone.
+//!
+//! States and video are read from a pcap capture of the drone traffic
+//! (`tcpdump -w`) or from a recording made by [`Recorder`](crate::recorder::Recorder),
+//! and sent to the state and video ports with the original timing.
+
+use std::{
+    fmt::{Display, Formatter},
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    net::SocketAddr,
+    path::Path,
+};
+
+use log::info;
+use tokio::{
+    net::UdpSocket,
+    time::{sleep_until, Duration, Instant},
+};
+
+use crate::{
+    h264::VIDEO_PACKET_SIZE,
+    recorder::{VideoIndexEntry, STATE_FILE, VIDEO_FILE, VIDEO_INDEX_FILE},
+    state::StateFrame,
+};
+
+/// Port the drone sends states to
+pub const TELLO_STATE_PORT: u16 = 8890;
+/// Port the drone sends video to
+pub const TELLO_VIDEO_PORT: u16 = 11111;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    State,
+    Video,
+}
+
+/// One UDP payload as sent by the drone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    /// Since the first packet of the capture
+    pub time: Duration,
+    pub stream: Stream,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// Not a pcap file, or an unsupported one
+    Pcap(String),
+    /// Malformed recording
+    Recording(String),
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read capture: {}", e),
+            Self::Pcap(e) => write!(f, "invalid pcap: {}", e),
+            Self::Recording(e) => write!(f, "invalid recording: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Loads a recording directory, or a pcap file otherwise.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Packet>, ReplayError> {
+    if path.as_ref().is_dir() {
+        load_recording(path)
+    } else {
+        load_pcap(path)
+    }
+}
+
+/// Reads the UDP payloads sent to [`TELLO_STATE_PORT`] and
+/// [`TELLO_VIDEO_PORT`] from a classic (not pcapng) capture file.
+pub fn load_pcap<P: AsRef<Path>>(path: P) -> Result<Vec<Packet>, ReplayError> {
+    let data = fs::read(path)?;
+    let header = data
+        .get(..24)
+        .ok_or_else(|| ReplayError::Pcap("file too short".to_string()))?;
+
+    let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let (big_endian, frac_per_sec) = match magic {
+        0xa1b2c3d4 => (false, 1_000_000),
+        0xa1b23c4d => (false, 1_000_000_000),
+        0xd4c3b2a1 => (true, 1_000_000),
+        0x4d3cb2a1 => (true, 1_000_000_000),
+        _ => return Err(ReplayError::Pcap(format!("unknown magic {:#010x}", magic))),
+    };
+    let read_u32 = |b: &[u8]| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    };
+    let link_type = read_u32(&header[20..24]);
+
+    let mut packets = Vec::new();
+    let mut rest = &data[24..];
+
+    // a truncated last record is normal when the capture was interrupted
+    while rest.len() >= 16 {
+        let ts_sec = read_u32(&rest[0..4]) as u64;
+        let ts_frac = read_u32(&rest[4..8]) as u64;
+        let len = read_u32(&rest[8..12]) as usize;
+        let Some(frame) = rest.get(16..16 + len) else {
+            break;
+        };
+        rest = &rest[16 + len..];
+
+        let Some((dst_port, payload)) = udp_payload(link_type, frame) else {
+            continue;
+        };
+        let stream = match dst_port {
+            TELLO_STATE_PORT => Stream::State,
+            TELLO_VIDEO_PORT => Stream::Video,
+            _ => continue,
+        };
+
+        packets.push(Packet {
+            time: Duration::from_secs(ts_sec)
+                + Duration::from_nanos(ts_frac * 1_000_000_000 / frac_per_sec),
+            stream,
+            data: payload.to_vec(),
+        });
+    }
+
+    Ok(normalize(packets))
+}
+
+/// Destination port and payload of an IPv4 UDP packet.
+fn udp_payload(link_type: u32, frame: &[u8]) -> Option<(u16, &[u8])> {
+    let ip = match link_type {
+        // BSD loopback, address family in host byte order
+        0 => match frame.get(..4)? {
+            [2, 0, 0, 0] | [0, 0, 0, 2] => &frame[4..],
+            _ => return None,
+        },
+        // Ethernet, with an optional VLAN tag
+        1 => match frame.get(12..14)? {
+            [0x08, 0x00] => &frame[14..],
+            [0x81, 0x00] if frame.get(16..18)? == [0x08, 0x00] => &frame[18..],
+            _ => return None,
+        },
+        // raw IP
+        12 | 101 => frame,
+        // Linux cooked capture (`tcpdump -i any`)
+        113 if frame.get(14..16)? == [0x08, 0x00] => &frame[16..],
+        276 if frame.get(0..2)? == [0x08, 0x00] => &frame[20..],
+        _ => return None,
+    };
+
+    // IPv4, UDP, not a fragment
+    let ihl = (*ip.first()? & 0x0f) as usize * 4;
+    let fragment = u16::from_be_bytes([*ip.get(6)?, *ip.get(7)?]) & 0x3fff;
+    if ip[0] >> 4 != 4 || *ip.get(9)? != 17 || fragment != 0 {
+        return None;
+    }
+
+    let udp = ip.get(ihl..)?;
+    let dst_port = u16::from_be_bytes([*udp.get(2)?, *udp.get(3)?]);
+    let len = u16::from_be_bytes([*udp.get(4)?, *udp.get(5)?]) as usize;
+    Some((dst_port, udp.get(8..len.max(8))?))
+}
+
+/// Reads a recording directory: states are sent in the drone's format, and
+/// video frames in packets of the drone's size.
+pub fn load_recording<P: AsRef<Path>>(dir: P) -> Result<Vec<Packet>, ReplayError> {
+    let dir = dir.as_ref();
+    let mut packets = Vec::new();
+
+    for line in BufReader::new(File::open(dir.join(STATE_FILE))?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let frame: StateFrame = serde_json::from_str(&line)
+            .map_err(|e| ReplayError::Recording(format!("{}: {}", STATE_FILE, e)))?;
+        packets.push(Packet {
+            time: Duration::from_millis(frame.timestamp_ms),
+            stream: Stream::State,
+            data: format!("{}\r\n", frame.state).into_bytes(),
+        });
+    }
+
+    let video = fs::read(dir.join(VIDEO_FILE))?;
+    for line in BufReader::new(File::open(dir.join(VIDEO_INDEX_FILE))?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry: VideoIndexEntry = serde_json::from_str(&line)
+            .map_err(|e| ReplayError::Recording(format!("{}: {}", VIDEO_INDEX_FILE, e)))?;
+        let data = video
+            .get(entry.offset as usize..(entry.offset + entry.size) as usize)
+            .ok_or_else(|| {
+                ReplayError::Recording(format!(
+                    "frame at {} is out of {}",
+                    entry.offset, VIDEO_FILE
+                ))
+            })?;
+
+        for chunk in data.chunks(VIDEO_PACKET_SIZE) {
+            packets.push(Packet {
+                time: Duration::from_millis(entry.timestamp_ms),
+                stream: Stream::Video,
+                data: chunk.to_vec(),
+            });
+        }
+    }
+
+    Ok(normalize(packets))
+}
+
+// sort by time (keeping the order of packets sent at once) and start at zero
+fn normalize(mut packets: Vec<Packet>) -> Vec<Packet> {
+    packets.sort_by_key(|p| p.time);
+    if let Some(start) = packets.first().map(|p| p.time) {
+        for p in packets.iter_mut() {
+            p.time -= start;
+        }
+    }
+    packets
+}
+
+/// Sends `packets` to the targets at their original timing divided by
+/// `speed`, e.g. 2.0 plays twice as fast. Fails with `InvalidInput` unless
+/// `speed` is positive.
+pub async fn replay(
+    packets: &[Packet],
+    state_target: SocketAddr,
+    video_target: SocketAddr,
+    speed: f64,
+) -> io::Result<()> {
+    if speed.is_nan() || speed <= 0.0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("replay speed must be positive, got {}", speed),
+        ));
+    }
+
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    let start = Instant::now();
+
+    for packet in packets {
+        // a tiny speed can stretch the capture beyond what an `Instant` holds
+        let at = Duration::try_from_secs_f64(packet.time.as_secs_f64() / speed)
+            .ok()
+            .and_then(|t| start.checked_add(t))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("replay speed {} is too slow", speed),
+                )
+            })?;
+        sleep_until(at).await;
+
+        let target = match packet.stream {
+            Stream::State => state_target,
+            Stream::Video => video_target,
+        };
+        socket.send_to(&packet.data, target).await?;
+    }
+
+    info!(
+        "replay: Sent {} packets in {:.1}s",
+        packets.len(),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
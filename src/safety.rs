@@ -0,0 +1,426 @@
+//! Safety supervisor: watches the states and the command clients, and
+//! warns, refuses takeoff or lands when a failsafe trips. Every intervention
+//! is published as an event.
+
+use std::{
+    fmt::{Display, Formatter},
+    mem::discriminant,
+    sync::{Arc, Mutex},
+};
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::AsyncWriteExt,
+    net::TcpListener,
+    select, spawn,
+    sync::{broadcast, mpsc, watch},
+    task::JoinSet,
+    time::{interval, Duration, Instant, MissedTickBehavior},
+};
+
+use crate::{
+    cmd::Command,
+    config::SafetyConfig,
+    control::accept_client,
+    flight::{Flight, FlightStatus},
+    proxy::{dispatch, CmdRequest},
+    state::{timestamp_ms, State, StateFrame},
+};
+
+// events buffered per client before it starts skipping
+const EVENT_CHANNEL_CAPACITY: usize = 32;
+// how far above `min_battery` the battery must be to clear the failsafe
+const BATTERY_HYSTERESIS: usize = 3;
+// wait before sending `land` again while still airborne, doubled each time
+const LAND_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_LAND_RETRY_DELAY: Duration = Duration::from_secs(8);
+
+/// What happens when a failsafe trips. Each one includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyAction {
+    Warn,
+    /// Takeoff is refused until the failsafe clears
+    RefuseTakeoff,
+    /// Lands if airborne, and refuses takeoff
+    Land,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "trigger", rename_all = "snake_case")]
+pub enum Trigger {
+    LowBattery { battery: usize },
+    HighTemperature { temperature: usize },
+    StateTimeout { since_ms: u64 },
+    LinkLost,
+}
+
+impl Display for Trigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LowBattery { battery } => write!(f, "low battery ({}%)", battery),
+            Self::HighTemperature { temperature } => {
+                write!(f, "high temperature ({}°C)", temperature)
+            }
+            Self::StateTimeout { since_ms } => write!(f, "no state for {}ms", since_ms),
+            Self::LinkLost => write!(f, "all command clients disconnected"),
+        }
+    }
+}
+
+/// An intervention, one JSON object per line on the event port.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafetyEvent {
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub trigger: Trigger,
+    pub action: SafetyAction,
+    /// What was done, e.g. `landing`
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+struct Status {
+    last_state: Option<Instant>,
+    /// When the last command client left while airborne
+    link_lost_at: Option<Instant>,
+    /// When to send `land` again, and the wait after that
+    land_retry: Option<(Instant, Duration)>,
+    /// Failsafes tripped and not cleared yet
+    active: Vec<(Trigger, SafetyAction)>,
+}
+
+impl Status {
+    fn position(&self, trigger: &Trigger) -> Option<usize> {
+        self.active
+            .iter()
+            .position(|(t, _)| discriminant(t) == discriminant(trigger))
+    }
+
+    fn is_active(&self, trigger: &Trigger) -> bool {
+        self.position(trigger).is_some()
+    }
+}
+
+/// Shared failsafe state: fed by [`supervise`], consulted by the dispatcher
+/// before every command. Whether the drone is in the air comes from `flight`.
+#[derive(Debug, Clone)]
+pub struct Safety {
+    config: Arc<SafetyConfig>,
+    flight: Flight,
+    status: Arc<Mutex<Status>>,
+    clients_tx: Arc<watch::Sender<usize>>,
+    event_tx: broadcast::Sender<SafetyEvent>,
+}
+
+/// Held for every connected command client.
+#[derive(Debug)]
+pub struct ClientLink(Arc<watch::Sender<usize>>);
+
+impl Drop for ClientLink {
+    fn drop(&mut self) {
+        self.0.send_modify(|clients| *clients -= 1);
+    }
+}
+
+impl Safety {
+    pub fn new(config: SafetyConfig, flight: Flight) -> Self {
+        let (clients_tx, _) = watch::channel(0);
+        let (event_tx, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+
+        Self {
+            config: Arc::new(config),
+            flight,
+            status: Arc::new(Mutex::new(Status::default())),
+            clients_tx: Arc::new(clients_tx),
+            event_tx,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SafetyEvent> {
+        self.event_tx.subscribe()
+    }
+
+    /// The failsafes tripped and not cleared yet.
+    pub fn active(&self) -> Vec<(Trigger, SafetyAction)> {
+        self.status.lock().unwrap().active.clone()
+    }
+
+    /// Registers a command client until the link is dropped.
+    pub fn link(&self) -> ClientLink {
+        self.clients_tx.send_modify(|clients| *clients += 1);
+        ClientLink(self.clients_tx.clone())
+    }
+
+    /// Refuses takeoff while a failsafe that forbids it is tripped.
+    pub fn check(&self, cmd: &Command) -> Result<(), String> {
+        if !matches!(cmd, Command::Takeoff | Command::ThrowFly) {
+            return Ok(());
+        }
+
+        let status = self.status.lock().unwrap();
+        match status
+            .active
+            .iter()
+            .find(|(_, action)| *action >= SafetyAction::RefuseTakeoff)
+        {
+            Some((trigger, _)) => {
+                self.publish(trigger, SafetyAction::RefuseTakeoff, "refused takeoff");
+                Err(trigger.to_string())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // returns whether the drone has to land
+    fn on_state(&self, state: &State) -> bool {
+        let mut status = self.status.lock().unwrap();
+        status.last_state = Some(Instant::now());
+
+        let config = &self.config;
+        let low_battery = if status.is_active(&Trigger::LowBattery { battery: 0 }) {
+            state.battery < config.min_battery + BATTERY_HYSTERESIS
+        } else {
+            state.battery < config.min_battery
+        };
+        let mut land = self.update(
+            &mut status,
+            Trigger::StateTimeout { since_ms: 0 },
+            false,
+            config.state_timeout_action,
+        );
+        land |= self.update(
+            &mut status,
+            Trigger::LowBattery {
+                battery: state.battery,
+            },
+            low_battery,
+            config.battery_action,
+        );
+        land |= self.update(
+            &mut status,
+            Trigger::HighTemperature {
+                temperature: state.temp_high,
+            },
+            state.temp_high > config.max_temperature,
+            config.temperature_action,
+        );
+        land
+    }
+
+    fn on_tick(&self) -> bool {
+        let mut status = self.status.lock().unwrap();
+        let timeout = Duration::from_millis(self.config.state_timeout_ms);
+
+        // nothing to miss before the first state
+        let land = match status.last_state {
+            Some(last_state) if !timeout.is_zero() && last_state.elapsed() > timeout => {
+                let since_ms = last_state.elapsed().as_millis() as u64;
+                self.update(
+                    &mut status,
+                    Trigger::StateTimeout { since_ms },
+                    true,
+                    self.config.state_timeout_action,
+                )
+            }
+            _ => false,
+        };
+        land | self.check_link(&mut status) | self.retry_land(&mut status)
+    }
+
+    fn on_clients(&self, clients: usize) -> bool {
+        let mut status = self.status.lock().unwrap();
+        if clients == 0 && self.flight.status().in_air() {
+            status.link_lost_at.get_or_insert_with(Instant::now);
+            self.check_link(&mut status)
+        } else {
+            status.link_lost_at = None;
+            self.update(
+                &mut status,
+                Trigger::LinkLost,
+                false,
+                self.config.link_lost_action,
+            )
+        }
+    }
+
+    // trips once no client came back within the grace period
+    fn check_link(&self, status: &mut Status) -> bool {
+        let grace = Duration::from_millis(self.config.link_lost_grace_ms);
+        if status.link_lost_at.is_none_or(|t| t.elapsed() < grace) {
+            return false;
+        }
+
+        self.update(
+            status,
+            Trigger::LinkLost,
+            true,
+            self.config.link_lost_action,
+        )
+    }
+
+    // `land` again while a failsafe that lands is tripped and the last one
+    // left the drone airborne, e.g. it timed out or was answered `error`
+    fn retry_land(&self, status: &mut Status) -> bool {
+        let landing = status
+            .active
+            .iter()
+            .any(|(_, action)| *action == SafetyAction::Land);
+        let flight = self.flight.status();
+        if !landing || !flight.in_air() {
+            status.land_retry = None;
+            return false;
+        }
+        // taking off, or the last `land` is not answered yet
+        if flight != FlightStatus::Airborne {
+            return false;
+        }
+
+        let now = Instant::now();
+        match status.land_retry {
+            Some((at, _)) if now < at => false,
+            Some((_, delay)) => {
+                warn!("safety: Still airborne, landing again");
+                status.land_retry = Some((now + delay, (delay * 2).min(MAX_LAND_RETRY_DELAY)));
+                true
+            }
+            None => {
+                status.land_retry = Some((now + LAND_RETRY_DELAY, LAND_RETRY_DELAY * 2));
+                false
+            }
+        }
+    }
+
+    // raises or clears `trigger`, returns whether the drone has to land
+    fn update(
+        &self,
+        status: &mut Status,
+        trigger: Trigger,
+        tripped: bool,
+        action: SafetyAction,
+    ) -> bool {
+        let pos = status.position(&trigger);
+
+        match (pos, tripped) {
+            (None, true) => {
+                let land = action == SafetyAction::Land && self.flight.status().in_air();
+                let message = match action {
+                    SafetyAction::Warn => "warning",
+                    SafetyAction::Land if land => "landing",
+                    _ => "takeoff refused until cleared",
+                };
+                self.publish(&trigger, action, message);
+                status.active.push((trigger, action));
+                land
+            }
+            (Some(pos), true) => {
+                // keep the latest value
+                status.active[pos].0 = trigger;
+                false
+            }
+            (Some(pos), false) => {
+                let (trigger, _) = status.active.remove(pos);
+                info!("safety: Cleared {}", trigger);
+                false
+            }
+            (None, false) => false,
+        }
+    }
+
+    fn publish(&self, trigger: &Trigger, action: SafetyAction, message: &str) {
+        warn!("safety: {}: {}", trigger, message);
+
+        // no subscribers is not an error
+        let _ = self.event_tx.send(SafetyEvent {
+            timestamp_ms: timestamp_ms(),
+            trigger: trigger.clone(),
+            action,
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Feeds the states and the client count to `safety`, and queues `land`
+/// when a failsafe asks for it.
+pub async fn supervise(
+    safety: Safety,
+    mut state_rx: broadcast::Receiver<StateFrame>,
+    req_tx: mpsc::Sender<CmdRequest>,
+) {
+    let mut clients_rx = safety.clients_tx.subscribe();
+    let mut ticker = interval(Duration::from_millis(100));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let land = select! {
+            frame = state_rx.recv() => match frame {
+                Ok(frame) => safety.on_state(&frame.state),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = ticker.tick() => safety.on_tick(),
+            res = clients_rx.changed() => {
+                if res.is_err() {
+                    return;
+                }
+                let clients = *clients_rx.borrow();
+                safety.on_clients(clients)
+            }
+        };
+
+        if land {
+            let req_tx = req_tx.clone();
+            spawn(async move {
+                match dispatch(&req_tx, Command::Land).await {
+                    Ok(res) => info!("safety: land -> {:?}", res),
+                    Err(e) => error!("safety: land failed: {}", e),
+                }
+            });
+        }
+    }
+}
+
+pub async fn listen_and_send_events(
+    listener: TcpListener,
+    safety: Safety,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // multi clients, closed when the listener stops
+    let mut clients = JoinSet::new();
+    loop {
+        info!("listen events: Waiting connection...");
+        let (mut stream, addr) = match accept_client(&listener, &mut clients).await {
+            Ok(r) => r,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let mut event_rx = safety.subscribe();
+        info!("listen events: Connected from {}", addr);
+
+        clients.spawn(async move {
+            loop {
+                let event = match event_rx.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!(
+                            "listen events: Client ({}) is too slow, skipped {} events",
+                            addr, n
+                        );
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                let json = format!("{}\n", serde_json::to_string(&event).unwrap());
+                if let Err(e) = stream.write_all(json.as_bytes()).await {
+                    error!(
+                        "listen events: Failed to send data to client ({}): {:?}",
+                        addr, e
+                    );
+                    break;
+                }
+            }
+            info!("listen events: End of connection with client ({})", addr);
+        });
+    }
+}
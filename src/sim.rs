@@ -0,0 +1,531 @@
+//! A simulated Tello speaking the SDK UDP protocol, for testing without a
+//! drone.
+//!
+//! The model is kinematic only: moves run at constant speed and are answered
+//! once they are done, `rc` sets velocities directly, and the battery drains
+//! linearly. Positions are in cm from the takeoff point, x forward, y left
+//! and z up as seen at takeoff; yaw is in degrees, clockwise. The speeds in
+//! the states are in the body frame.
+
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use log::{info, warn};
+use tokio::{
+    net::UdpSocket,
+    spawn,
+    sync::{oneshot, watch},
+    time::{interval, Duration, Instant, MissedTickBehavior},
+};
+
+use crate::{
+    cmd::{Command, ExtCommand},
+    state::{PointState, State},
+};
+
+/// How often states are sent, as the drone does
+pub const STATE_INTERVAL: Duration = Duration::from_millis(100);
+
+const SERIAL_NUMBER: &str = "0TQSIMULATOR";
+const TAKEOFF_HEIGHT: f64 = 80.0;
+// cm/s, for takeoff and landing
+const VERTICAL_SPEED: f64 = 40.0;
+// deg/s, for cw/ccw
+const YAW_RATE: f64 = 90.0;
+const FLIP_DURATION: Duration = Duration::from_secs(1);
+const DEFAULT_SPEED: f64 = 100.0;
+// %/s, a full battery lasts 12 min in the air and an hour on the ground
+const FLYING_DRAIN: f64 = 100.0 / 720.0;
+const IDLE_DRAIN: f64 = 100.0 / 3600.0;
+const MIN_TAKEOFF_BATTERY: f64 = 10.0;
+// barometer reading on the ground, in m
+const GROUND_BAROMETER: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Vec3 {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Vec3 {
+    fn scale(self, k: f64) -> Self {
+        Self {
+            x: self.x * k,
+            y: self.y * k,
+            z: self.z * k,
+        }
+    }
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+
+    fn length(self) -> f64 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+}
+
+/// A move in progress, answered through `done` when it finishes (`true`)
+/// or is interrupted (`false`).
+#[derive(Debug)]
+struct Motion {
+    velocity: Vec3,
+    yaw_rate: f64,
+    remaining: Duration,
+    lands: bool,
+    done: Option<oneshot::Sender<bool>>,
+}
+
+/// How the drone answers a command.
+#[derive(Debug)]
+pub enum Reply {
+    Now(String),
+    /// `ok` once the move is done, `error` if it gets interrupted
+    AfterMotion(oneshot::Receiver<bool>),
+    /// `rc`, and anything sent before `command`
+    None,
+}
+
+#[derive(Debug)]
+pub struct Simulator {
+    sdk_mode: bool,
+    flying: bool,
+    position: Vec3,
+    yaw: f64,
+    // world frame, cm/s
+    velocity: Vec3,
+    battery: f64,
+    speed: f64,
+    rc: [f64; 4],
+    motor_time: Duration,
+    motion: Option<Motion>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self {
+            sdk_mode: false,
+            flying: false,
+            position: Vec3::default(),
+            yaw: 0.0,
+            velocity: Vec3::default(),
+            battery: 100.0,
+            speed: DEFAULT_SPEED,
+            rc: [0.0; 4],
+            motor_time: Duration::ZERO,
+            motion: None,
+        }
+    }
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_flying(&self) -> bool {
+        self.flying
+    }
+
+    /// `(x, y, z)` in cm from the takeoff point.
+    pub fn position(&self) -> (f64, f64, f64) {
+        (self.position.x, self.position.y, self.position.z)
+    }
+
+    pub fn yaw(&self) -> f64 {
+        self.yaw
+    }
+
+    pub fn battery(&self) -> f64 {
+        self.battery
+    }
+
+    pub fn set_battery(&mut self, battery: f64) {
+        self.battery = battery.clamp(0.0, 100.0);
+    }
+
+    pub fn command(&mut self, cmd: &Command) -> Reply {
+        // the drone ignores everything until it is put in SDK mode
+        if !self.sdk_mode {
+            if let Command::Command = cmd {
+                self.sdk_mode = true;
+                return ok();
+            }
+            return Reply::None;
+        }
+
+        match cmd {
+            Command::Takeoff if self.flying => error(),
+            Command::Takeoff if self.battery < MIN_TAKEOFF_BATTERY => error(),
+            Command::Takeoff => {
+                self.flying = true;
+                self.start_motion(
+                    Vec3 {
+                        z: TAKEOFF_HEIGHT,
+                        ..Default::default()
+                    },
+                    VERTICAL_SPEED,
+                    false,
+                )
+            }
+            Command::Land if !self.flying => error(),
+            Command::Land => self.land(),
+            Command::Emergency => {
+                self.cancel_motion();
+                self.flying = false;
+                self.position.z = 0.0;
+                self.velocity = Vec3::default();
+                ok()
+            }
+            Command::Stop => {
+                self.cancel_motion();
+                self.rc = [0.0; 4];
+                ok()
+            }
+            Command::Up(_)
+            | Command::Down(_)
+            | Command::Left(_)
+            | Command::Right(_)
+            | Command::Forward(_)
+            | Command::Back(_)
+            | Command::ClockwiseRotation(_)
+            | Command::CounterClockwiseRotation(_)
+            | Command::Flip(_)
+            | Command::Go { .. }
+            | Command::Curve { .. }
+            | Command::Jump { .. }
+                if !self.flying =>
+            {
+                Reply::Now("error Motor stop".to_string())
+            }
+            Command::Up(d) => self.move_body(0.0, 0.0, d.get() as f64, self.speed),
+            Command::Down(d) => self.move_body(0.0, 0.0, -(d.get() as f64), self.speed),
+            Command::Left(d) => self.move_body(0.0, d.get() as f64, 0.0, self.speed),
+            Command::Right(d) => self.move_body(0.0, -(d.get() as f64), 0.0, self.speed),
+            Command::Forward(d) => self.move_body(d.get() as f64, 0.0, 0.0, self.speed),
+            Command::Back(d) => self.move_body(-(d.get() as f64), 0.0, 0.0, self.speed),
+            Command::ClockwiseRotation(a) => self.rotate(a.get() as f64),
+            Command::CounterClockwiseRotation(a) => self.rotate(-(a.get() as f64)),
+            Command::Flip(_) => self.replace_motion(Motion {
+                velocity: Vec3::default(),
+                yaw_rate: 0.0,
+                remaining: FLIP_DURATION,
+                lands: false,
+                done: None,
+            }),
+            // there are no mission pads in the simulation
+            Command::Go { mid: Some(_), .. }
+            | Command::Curve { mid: Some(_), .. }
+            | Command::Jump { .. } => error(),
+            Command::Go { x, y, z, speed, .. } => self.move_body(
+                x.get() as f64,
+                y.get() as f64,
+                z.get() as f64,
+                speed.get() as f64,
+            ),
+            // flown as a straight line to the end point
+            Command::Curve {
+                x2, y2, z2, speed, ..
+            } => self.move_body(
+                x2.get() as f64,
+                y2.get() as f64,
+                z2.get() as f64,
+                speed.get() as f64,
+            ),
+            Command::Speed(speed) => {
+                self.speed = speed.get() as f64;
+                ok()
+            }
+            Command::Rc { a, b, c, d } => {
+                self.rc = [
+                    a.get() as f64,
+                    b.get() as f64,
+                    c.get() as f64,
+                    d.get() as f64,
+                ];
+                Reply::None
+            }
+            Command::ReadBattery => Reply::Now(format!("{}", self.battery.round())),
+            Command::ReadSpeed => Reply::Now(format!("{:.1}", self.speed)),
+            Command::ReadTime => Reply::Now(format!("{}s", self.motor_time.as_secs())),
+            Command::ReadWifi => Reply::Now("90".to_string()),
+            Command::ReadSdk => Reply::Now("30".to_string()),
+            Command::ReadSerialNumber => Reply::Now(SERIAL_NUMBER.to_string()),
+            Command::Ext(ExtCommand::ReadTof) => Reply::Now(format!("tof {}", self.tof())),
+            _ => ok(),
+        }
+    }
+
+    /// Advances the simulation by `dt`.
+    pub fn step(&mut self, dt: Duration) {
+        let drain = if self.flying {
+            FLYING_DRAIN
+        } else {
+            IDLE_DRAIN
+        };
+        self.battery = (self.battery - drain * dt.as_secs_f64()).max(0.0);
+
+        if self.flying {
+            self.motor_time += dt;
+
+            // the drone lands by itself on an empty battery
+            if self.battery <= 0.0 && !self.motion.as_ref().is_some_and(|m| m.lands) {
+                warn!("sim: Battery empty, landing");
+                self.land();
+            }
+        }
+
+        if let Some(motion) = self.motion.as_mut() {
+            let t = dt.min(motion.remaining);
+            self.velocity = motion.velocity;
+            self.position = self.position.add(motion.velocity.scale(t.as_secs_f64()));
+            self.yaw += motion.yaw_rate * t.as_secs_f64();
+            motion.remaining -= t;
+
+            if motion.remaining.is_zero() {
+                let motion = self.motion.take().unwrap();
+                self.velocity = Vec3::default();
+                if motion.lands {
+                    self.flying = false;
+                    self.position.z = 0.0;
+                }
+                if let Some(done) = motion.done {
+                    let _ = done.send(true);
+                }
+            }
+        } else if self.flying {
+            // a is right, b forward, c up and d clockwise, in cm/s and deg/s
+            let [a, b, c, d] = self.rc;
+            self.velocity = self.to_world(b, -a, c);
+            self.position = self.position.add(self.velocity.scale(dt.as_secs_f64()));
+            self.yaw += d * dt.as_secs_f64();
+        } else {
+            self.velocity = Vec3::default();
+        }
+
+        self.position.z = self.position.z.max(0.0);
+        // keep yaw in -180 ~ 180 like the drone reports it
+        self.yaw = (self.yaw + 180.0).rem_euclid(360.0) - 180.0;
+    }
+
+    /// The state as the drone would send it now.
+    pub fn state(&self) -> State {
+        State {
+            missionpad: None,
+            pitch: 0,
+            roll: 0,
+            yaw: self.yaw.round() as isize,
+            speeds: {
+                let speeds = self.to_body(self.velocity);
+                PointState {
+                    x: dm_per_s(speeds.x),
+                    y: dm_per_s(speeds.y),
+                    z: dm_per_s(speeds.z),
+                }
+            },
+            temp_low: 60,
+            temp_high: 63,
+            time_of_flight: self.tof(),
+            height: self.position.z.round() as usize,
+            battery: self.battery.round() as usize,
+            barometer: (GROUND_BAROMETER + self.position.z / 100.0) as f32,
+            time: self.motor_time.as_secs() as usize,
+            // mg, gravity only
+            accelerations: PointState {
+                x: 0.0,
+                y: 0.0,
+                z: -1000.0,
+            },
+        }
+    }
+
+    // the distance sensor sits a bit above the ground when landed
+    fn tof(&self) -> usize {
+        self.position.z.round() as usize + 10
+    }
+
+    /// Body frame (x forward, y left, z up) to the takeoff frame.
+    fn to_world(&self, x: f64, y: f64, z: f64) -> Vec3 {
+        let (sin, cos) = self.yaw.to_radians().sin_cos();
+        Vec3 {
+            x: x * cos + y * sin,
+            y: -x * sin + y * cos,
+            z,
+        }
+    }
+
+    /// The takeoff frame to the body frame, as the speeds are sent.
+    fn to_body(&self, v: Vec3) -> Vec3 {
+        let (sin, cos) = self.yaw.to_radians().sin_cos();
+        Vec3 {
+            x: v.x * cos - v.y * sin,
+            y: v.x * sin + v.y * cos,
+            z: v.z,
+        }
+    }
+
+    fn move_body(&mut self, x: f64, y: f64, z: f64, speed: f64) -> Reply {
+        let offset = self.to_world(x, y, z);
+        self.start_motion(offset, speed, false)
+    }
+
+    fn start_motion(&mut self, offset: Vec3, speed: f64, lands: bool) -> Reply {
+        let duration = offset.length() / speed;
+        let velocity = if duration > 0.0 {
+            offset.scale(1.0 / duration)
+        } else {
+            Vec3::default()
+        };
+
+        self.replace_motion(Motion {
+            velocity,
+            yaw_rate: 0.0,
+            remaining: Duration::from_secs_f64(duration),
+            lands,
+            done: None,
+        })
+    }
+
+    fn rotate(&mut self, angle: f64) -> Reply {
+        self.replace_motion(Motion {
+            velocity: Vec3::default(),
+            yaw_rate: YAW_RATE.copysign(angle),
+            remaining: Duration::from_secs_f64(angle.abs() / YAW_RATE),
+            lands: false,
+            done: None,
+        })
+    }
+
+    fn land(&mut self) -> Reply {
+        let offset = Vec3 {
+            z: -self.position.z,
+            ..Default::default()
+        };
+        self.start_motion(offset, VERTICAL_SPEED, true)
+    }
+
+    // a new move interrupts the one in progress
+    fn replace_motion(&mut self, mut motion: Motion) -> Reply {
+        self.cancel_motion();
+
+        let (done_tx, done_rx) = oneshot::channel();
+        motion.done = Some(done_tx);
+        self.motion = Some(motion);
+        Reply::AfterMotion(done_rx)
+    }
+
+    fn cancel_motion(&mut self) {
+        if let Some(done) = self.motion.take().and_then(|m| m.done) {
+            let _ = done.send(false);
+        }
+        self.velocity = Vec3::default();
+    }
+}
+
+// rounded like the drone does, without a "-0"
+fn dm_per_s(cm_per_s: f64) -> f32 {
+    ((cm_per_s / 10.0).round() + 0.0) as f32
+}
+
+fn ok() -> Reply {
+    Reply::Now("ok".to_string())
+}
+
+fn error() -> Reply {
+    Reply::Now("error".to_string())
+}
+
+/// Answers commands received on `socket` and steps the simulation. Once in
+/// SDK mode, states are sent every [`STATE_INTERVAL`] to `state_port` on the
+/// host that sent `command`.
+pub async fn serve(
+    socket: UdpSocket,
+    state_port: u16,
+    sim: Arc<Mutex<Simulator>>,
+) -> io::Result<()> {
+    let socket = Arc::new(socket);
+    let (state_target_tx, state_target_rx) = watch::channel(None);
+
+    spawn(send_state(sim.clone(), state_target_rx));
+
+    let mut buf = vec![0; 1024];
+    loop {
+        let (size, addr) = socket.recv_from(&mut buf).await?;
+        let s = String::from_utf8_lossy(&buf[..size]);
+
+        // doorbells
+        if s.trim().is_empty() {
+            continue;
+        }
+
+        let cmd = match s.trim().parse::<Command>() {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                info!("sim: Invalid command from {}: {}", addr, e);
+                socket.send_to(b"error", addr).await?;
+                continue;
+            }
+        };
+        info!("sim: Receive command from {}: {:?}", addr, cmd);
+
+        let reply = sim.lock().unwrap().command(&cmd);
+        if let Command::Command = cmd {
+            let _ = state_target_tx.send(Some(SocketAddr::new(addr.ip(), state_port)));
+        }
+
+        match reply {
+            Reply::Now(res) => {
+                socket.send_to(res.as_bytes(), addr).await?;
+            }
+            Reply::AfterMotion(done) => {
+                let socket = socket.clone();
+                spawn(async move {
+                    let res = match done.await {
+                        Ok(true) => "ok",
+                        _ => "error",
+                    };
+                    let _ = socket.send_to(res.as_bytes(), addr).await;
+                });
+            }
+            Reply::None => (),
+        }
+    }
+}
+
+async fn send_state(
+    sim: Arc<Mutex<Simulator>>,
+    state_target_rx: watch::Receiver<Option<SocketAddr>>,
+) -> io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    let mut ticker = interval(STATE_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last = Instant::now();
+
+    loop {
+        ticker.tick().await;
+
+        let now = Instant::now();
+        let state = {
+            let mut sim = sim.lock().unwrap();
+            sim.step(now - last);
+            sim.state()
+        };
+        last = now;
+
+        let target = *state_target_rx.borrow();
+        if let Some(target) = target {
+            // the state port may not be open yet
+            let _ = socket
+                .send_to(format!("{}\r\n", state).as_bytes(), target)
+                .await;
+        }
+    }
+}
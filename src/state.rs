@@ -1,6 +1,13 @@
-use serde::Serialize;
+use std::{
+    fmt::{Display, Formatter},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+use serde::{Deserialize, Serialize};
+
+use crate::{flight::FlightStatus, pose::Pose};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct PointState {
     pub x: f32,
     pub y: f32,
@@ -17,9 +24,42 @@ impl Default for PointState {
     }
 }
 
-// TODO: implement states for missionpad
-#[derive(Debug, Clone, PartialEq, Serialize)]
+/// Mission pad fields, only sent by the drone while `mon` is enabled.
+/// `mid` is -1 (and x/y/z are -100) when no pad is detected.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MissionpadState {
+    pub mid: isize,
+    pub x: isize,
+    pub y: isize,
+    pub z: isize,
+    pub pitch: isize,
+    pub roll: isize,
+    pub yaw: isize,
+}
+
+impl Default for MissionpadState {
+    fn default() -> Self {
+        Self {
+            mid: -1,
+            x: -100,
+            y: -100,
+            z: -100,
+            pitch: 0,
+            roll: 0,
+            yaw: 0,
+        }
+    }
+}
+
+impl MissionpadState {
+    pub fn is_detected(&self) -> bool {
+        self.mid > 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct State {
+    pub missionpad: Option<MissionpadState>,
     pub pitch: isize,
     pub roll: isize,
     pub yaw: isize,
@@ -37,6 +77,7 @@ pub struct State {
 impl Default for State {
     fn default() -> Self {
         Self {
+            missionpad: None,
             pitch: 0,
             roll: 0,
             yaw: 0,
@@ -54,14 +95,18 @@ impl Default for State {
 }
 
 impl State {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         let mut state = Self::default();
 
         let fields: Vec<&str> = s.trim().split(';').collect();
 
-        if fields.len() != 17 {
-            return None;
-        }
+        // 16 fields (+ trailing empty one), or 21 with the mission pad block
+        let mut missionpad = match fields.len() {
+            17 => None,
+            22 => Some(MissionpadState::default()),
+            _ => return None,
+        };
 
         let mut point_state = PointState::default();
 
@@ -72,27 +117,42 @@ impl State {
                 continue;
             }
 
-            match parts[0] {
-                "pitch" => state.pitch = parts[1].parse().ok()?,
-                "roll" => state.roll = parts[1].parse().ok()?,
-                "yaw" => state.yaw = parts[1].parse().ok()?,
-                "vgx" => point_state.x = parts[1].parse().ok()?,
-                "vgy" => point_state.y = parts[1].parse().ok()?,
-                "vgz" => {
+            match (parts[0], missionpad.as_mut()) {
+                ("mid", Some(pad)) => pad.mid = parts[1].parse().ok()?,
+                ("x", Some(pad)) => pad.x = parts[1].parse().ok()?,
+                ("y", Some(pad)) => pad.y = parts[1].parse().ok()?,
+                ("z", Some(pad)) => pad.z = parts[1].parse().ok()?,
+                ("mpry", Some(pad)) => {
+                    let values: Vec<&str> = parts[1].split(',').collect();
+
+                    if values.len() != 3 {
+                        return None;
+                    }
+
+                    pad.pitch = values[0].parse().ok()?;
+                    pad.roll = values[1].parse().ok()?;
+                    pad.yaw = values[2].parse().ok()?;
+                }
+                ("pitch", _) => state.pitch = parts[1].parse().ok()?,
+                ("roll", _) => state.roll = parts[1].parse().ok()?,
+                ("yaw", _) => state.yaw = parts[1].parse().ok()?,
+                ("vgx", _) => point_state.x = parts[1].parse().ok()?,
+                ("vgy", _) => point_state.y = parts[1].parse().ok()?,
+                ("vgz", _) => {
                     point_state.z = parts[1].parse().ok()?;
                     state.speeds = point_state;
                     point_state = PointState::default();
                 }
-                "templ" => state.temp_low = parts[1].parse().ok()?,
-                "temph" => state.temp_high = parts[1].parse().ok()?,
-                "tof" => state.time_of_flight = parts[1].parse().ok()?,
-                "h" => state.height = parts[1].parse().ok()?,
-                "bat" => state.battery = parts[1].parse().ok()?,
-                "baro" => state.barometer = parts[1].parse().ok()?,
-                "time" => state.time = parts[1].parse().ok()?,
-                "agx" => point_state.x = parts[1].parse().ok()?,
-                "agy" => point_state.y = parts[1].parse().ok()?,
-                "agz" => {
+                ("templ", _) => state.temp_low = parts[1].parse().ok()?,
+                ("temph", _) => state.temp_high = parts[1].parse().ok()?,
+                ("tof", _) => state.time_of_flight = parts[1].parse().ok()?,
+                ("h", _) => state.height = parts[1].parse().ok()?,
+                ("bat", _) => state.battery = parts[1].parse().ok()?,
+                ("baro", _) => state.barometer = parts[1].parse().ok()?,
+                ("time", _) => state.time = parts[1].parse().ok()?,
+                ("agx", _) => point_state.x = parts[1].parse().ok()?,
+                ("agy", _) => point_state.y = parts[1].parse().ok()?,
+                ("agz", _) => {
                     point_state.z = parts[1].parse().ok()?;
                     state.accelerations = point_state;
                     point_state = PointState::default();
@@ -101,6 +161,81 @@ impl State {
             }
         }
 
-        return Some(state);
+        state.missionpad = missionpad;
+
+        Some(state)
+    }
+}
+
+/// Formats the state the way the drone sends it (without the trailing
+/// `\r\n`), so that it parses back with [`State::from_str`].
+impl Display for State {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(pad) = &self.missionpad {
+            write!(
+                f,
+                "mid:{};x:{};y:{};z:{};mpry:{},{},{};",
+                pad.mid, pad.x, pad.y, pad.z, pad.pitch, pad.roll, pad.yaw
+            )?;
+        }
+
+        write!(
+            f,
+            "pitch:{};roll:{};yaw:{};vgx:{};vgy:{};vgz:{};templ:{};temph:{};tof:{};h:{};bat:{};baro:{:.2};time:{};agx:{:.2};agy:{:.2};agz:{:.2};",
+            self.pitch,
+            self.roll,
+            self.yaw,
+            self.speeds.x,
+            self.speeds.y,
+            self.speeds.z,
+            self.temp_low,
+            self.temp_high,
+            self.time_of_flight,
+            self.height,
+            self.battery,
+            self.barometer,
+            self.time,
+            self.accelerations.x,
+            self.accelerations.y,
+            self.accelerations.z,
+        )
     }
 }
+
+/// A parsed state as sent to clients, one JSON object per line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateFrame {
+    /// Increases by one for every state received from the drone, so gaps
+    /// mean dropped packets.
+    pub seq: u64,
+    /// Host receive time in milliseconds since the UNIX epoch
+    pub timestamp_ms: u64,
+    pub state: State,
+    /// Estimated from the states so far, missing in older recordings
+    #[serde(default)]
+    pub pose: Pose,
+    #[serde(default)]
+    pub flight: FlightStatus,
+}
+
+impl StateFrame {
+    /// Stamps `state` with the current host time.
+    pub fn new(seq: u64, state: State) -> Self {
+        Self {
+            seq,
+            timestamp_ms: timestamp_ms(),
+            state,
+            pose: Pose::default(),
+            flight: FlightStatus::default(),
+        }
+    }
+}
+
+/// Current host time in milliseconds since the UNIX epoch, the clock shared
+/// by state frames and recordings.
+pub fn timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
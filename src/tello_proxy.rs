@@ -0,0 +1,464 @@
+//! The whole proxy behind one handle: every port is bound up front, the
+//! relays run as tasks and are shut down together.
+
+use std::{
+    fmt::{Display, Formatter},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use log::{info, warn};
+use tokio::{
+    net::{TcpListener, UdpSocket},
+    select,
+    sync::{broadcast, mpsc, watch},
+    task::JoinSet,
+    time::Duration,
+};
+
+use crate::{
+    cmd::Command,
+    config::{
+        Config, ConfigError, FlightConfig, GeofenceConfig, ListenConfig, SafetyConfig, TelloConfig,
+    },
+    control::listen_record_control,
+    flight::Flight,
+    geofence::Geofence,
+    proxy::{
+        dispatch, dispatch_cmd, listen_and_send_cmd, listen_and_send_rpc, CmdGuards, CmdRequest,
+        DispatchError, Framing,
+    },
+    recorder::{Recorder, RecorderError},
+    relay::{listen_and_send_state, recv_state, STATE_CHANNEL_CAPACITY},
+    safety::{listen_and_send_events, supervise, Safety},
+    state::StateFrame,
+    video::{listen_and_stream_video, listen_video_control, VideoSubscribers},
+};
+
+#[derive(Debug)]
+pub enum ProxyError {
+    Config(ConfigError),
+    Io(std::io::Error),
+    Record(RecorderError),
+    /// A relay stopped on its own
+    Task(String),
+}
+
+impl Display for ProxyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Config(e) => write!(f, "{}", e),
+            Self::Io(e) => write!(f, "failed to open port: {}", e),
+            Self::Record(e) => write!(f, "{}", e),
+            Self::Task(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+impl From<std::io::Error> for ProxyError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<RecorderError> for ProxyError {
+    fn from(e: RecorderError) -> Self {
+        Self::Record(e)
+    }
+}
+
+/// Settings of a [`TelloProxy`], the defaults are those of [`Config`].
+#[derive(Debug, Clone)]
+pub struct TelloProxyBuilder {
+    config: Config,
+    handshake: bool,
+}
+
+impl Default for TelloProxyBuilder {
+    fn default() -> Self {
+        Self {
+            config: Config::default(),
+            handshake: true,
+        }
+    }
+}
+
+impl TelloProxyBuilder {
+    /// Replaces every setting read from a config file.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn res_timeout(mut self, res_timeout: Duration) -> Self {
+        self.config.res_timeout_ms = res_timeout.as_millis() as u64;
+        self
+    }
+
+    /// Idle time before a keepalive command, `None` to disable.
+    pub fn keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.config.keepalive_ms = keepalive.map_or(0, |t| t.as_millis() as u64);
+        self
+    }
+
+    /// Ports served to clients; port 0 picks a free one, see
+    /// [`TelloProxy::listen_addrs`].
+    pub fn listen(mut self, listen: ListenConfig) -> Self {
+        self.config.listen = listen;
+        self
+    }
+
+    pub fn tello(mut self, tello: TelloConfig) -> Self {
+        self.config.tello = tello;
+        self
+    }
+
+    pub fn video_subscribers(mut self, subscribers: Vec<SocketAddr>) -> Self {
+        self.config.video.subscribers = subscribers;
+        self
+    }
+
+    pub fn video_subscriber_ttl(mut self, ttl: Duration) -> Self {
+        self.config.video.subscriber_ttl_ms = ttl.as_millis() as u64;
+        self
+    }
+
+    pub fn record_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.config.record.dir = dir.into();
+        self
+    }
+
+    /// Start recording right away.
+    pub fn record(mut self, autostart: bool) -> Self {
+        self.config.record.autostart = autostart;
+        self
+    }
+
+    pub fn safety(mut self, safety: SafetyConfig) -> Self {
+        self.config.safety = safety;
+        self
+    }
+
+    pub fn geofence(mut self, geofence: GeofenceConfig) -> Self {
+        self.config.geofence = geofence;
+        self
+    }
+
+    pub fn flight(mut self, flight: FlightConfig) -> Self {
+        self.config.flight = flight;
+        self
+    }
+
+    /// Whether `command` and `streamon` are sent once the proxy is up, on by
+    /// default.
+    pub fn handshake(mut self, handshake: bool) -> Self {
+        self.handshake = handshake;
+        self
+    }
+
+    /// Binds every port and starts the relays. Fails without leaving
+    /// anything running if the config is invalid or a port is taken.
+    pub async fn start(self) -> Result<TelloProxy, ProxyError> {
+        let config = self.config;
+        config.validate().map_err(ProxyError::Config)?;
+        let res_timeout = Duration::from_millis(config.res_timeout_ms);
+        let keepalive = match config.keepalive_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+
+        // bind first, so nothing sent before the relays run is lost
+        let cmd_socket = UdpSocket::bind("0.0.0.0:0").await?;
+        let cmd_listener = TcpListener::bind(config.listen.cmd).await?;
+        let legacy_cmd_listener = TcpListener::bind(config.listen.legacy_cmd).await?;
+        let rpc_listener = TcpListener::bind(config.listen.rpc).await?;
+        let state_socket = UdpSocket::bind(config.tello.state).await?;
+        let state_listener = TcpListener::bind(config.listen.state).await?;
+        let video_socket = UdpSocket::bind(config.tello.video).await?;
+        let video_control_listener = TcpListener::bind(config.listen.video_control).await?;
+        let record_control_listener = TcpListener::bind(config.listen.record_control).await?;
+        let events_listener = TcpListener::bind(config.listen.events).await?;
+
+        let listen = ListenConfig {
+            cmd: cmd_listener.local_addr()?,
+            legacy_cmd: legacy_cmd_listener.local_addr()?,
+            state: state_listener.local_addr()?,
+            rpc: rpc_listener.local_addr()?,
+            video_control: video_control_listener.local_addr()?,
+            record_control: record_control_listener.local_addr()?,
+            events: events_listener.local_addr()?,
+        };
+        let tello = TelloConfig {
+            state: state_socket.local_addr()?,
+            video: video_socket.local_addr()?,
+            ..config.tello.clone()
+        };
+
+        let recorder = Recorder::new(&config.record.dir);
+        if config.record.autostart {
+            recorder.start()?;
+        }
+
+        let flight = Flight::new(config.flight.clone());
+        let safety = Safety::new(config.safety.clone(), flight.clone());
+        let geofence = Geofence::new(config.geofence.clone(), flight.clone());
+        let mut tasks = JoinSet::new();
+
+        // recording
+        tasks.spawn(run(
+            "listen record control",
+            listen_record_control(record_control_listener, recorder.clone()),
+        ));
+
+        // command
+        let (req_tx, req_rx) = mpsc::channel(32);
+        let dispatch_recorder = recorder.clone();
+        let guards = CmdGuards {
+            safety: safety.clone(),
+            flight: flight.clone(),
+            geofence: geofence.clone(),
+        };
+        tasks.spawn(async move {
+            dispatch_cmd(
+                cmd_socket,
+                tello.cmd,
+                res_timeout,
+                keepalive,
+                req_rx,
+                dispatch_recorder,
+                guards,
+            )
+            .await;
+            Ok(())
+        });
+        tasks.spawn(run(
+            "listen cmd",
+            listen_and_send_cmd(cmd_listener, Framing::Line, req_tx.clone(), safety.clone()),
+        ));
+        tasks.spawn(run(
+            "listen legacy cmd",
+            listen_and_send_cmd(
+                legacy_cmd_listener,
+                Framing::Legacy,
+                req_tx.clone(),
+                safety.clone(),
+            ),
+        ));
+        tasks.spawn(run(
+            "listen rpc",
+            listen_and_send_rpc(rpc_listener, req_tx.clone(), safety.clone()),
+        ));
+
+        // state
+        let (state_tx, _) = broadcast::channel(STATE_CHANNEL_CAPACITY);
+        tasks.spawn(run(
+            "receive state",
+            recv_state(
+                state_socket,
+                tello.cmd,
+                res_timeout,
+                state_tx.clone(),
+                recorder.clone(),
+                flight.clone(),
+            ),
+        ));
+        tasks.spawn(run(
+            "listen state",
+            listen_and_send_state(state_listener, state_tx.clone()),
+        ));
+
+        // video
+        let subscribers = VideoSubscribers::new(&config.video.subscribers);
+        tasks.spawn(run(
+            "listen video",
+            listen_and_stream_video(
+                video_socket,
+                tello.video_doorbell,
+                subscribers.clone(),
+                recorder.clone(),
+            ),
+        ));
+        tasks.spawn(run(
+            "listen video control",
+            listen_video_control(
+                video_control_listener,
+                subscribers.clone(),
+                Duration::from_millis(config.video.subscriber_ttl_ms),
+            ),
+        ));
+
+        // safety
+        let supervise_safety = safety.clone();
+        let supervise_state_rx = state_tx.subscribe();
+        let supervise_req_tx = req_tx.clone();
+        tasks.spawn(async move {
+            supervise(supervise_safety, supervise_state_rx, supervise_req_tx).await;
+            Ok(())
+        });
+        tasks.spawn(run(
+            "listen events",
+            listen_and_send_events(events_listener, safety.clone()),
+        ));
+
+        if self.handshake {
+            tasks.spawn(handshake(req_tx.clone()));
+        }
+
+        info!("proxy: Started, commands on {}", listen.cmd);
+
+        let (stop_tx, stop_rx) = watch::channel(false);
+        Ok(TelloProxy {
+            listen,
+            tello,
+            req_tx,
+            state_tx,
+            subscribers,
+            recorder,
+            safety,
+            flight,
+            geofence,
+            stop_tx: Arc::new(stop_tx),
+            stop_rx,
+            tasks,
+        })
+    }
+}
+
+async fn run<F>(name: &'static str, task: F) -> Result<(), String>
+where
+    F: std::future::Future<Output = Result<(), Box<dyn std::error::Error>>>,
+{
+    task.await.map_err(|e| format!("{}: {}", name, e))
+}
+
+// goes through the dispatcher, so `streamon` is only sent once `command` is answered
+async fn handshake(req_tx: mpsc::Sender<CmdRequest>) -> Result<(), String> {
+    for cmd in [Command::Command, Command::StreamOn] {
+        match dispatch(&req_tx, cmd.clone()).await {
+            Ok(res) => info!("handshake: {} -> {:?}", cmd, res),
+            Err(e) => warn!("handshake: {} failed: {}", cmd, e),
+        }
+    }
+    Ok(())
+}
+
+/// Asks a running [`TelloProxy`] to stop, from anywhere.
+#[derive(Debug, Clone)]
+pub struct StopHandle(Arc<watch::Sender<bool>>);
+
+impl StopHandle {
+    pub fn stop(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// A running proxy. Dropping it aborts the relays without flushing the
+/// recording; [`TelloProxy::stop`] shuts down cleanly.
+pub struct TelloProxy {
+    listen: ListenConfig,
+    tello: TelloConfig,
+    req_tx: mpsc::Sender<CmdRequest>,
+    state_tx: broadcast::Sender<StateFrame>,
+    subscribers: VideoSubscribers,
+    recorder: Recorder,
+    safety: Safety,
+    flight: Flight,
+    geofence: Geofence,
+    stop_tx: Arc<watch::Sender<bool>>,
+    stop_rx: watch::Receiver<bool>,
+    tasks: JoinSet<Result<(), String>>,
+}
+
+impl TelloProxy {
+    pub fn builder() -> TelloProxyBuilder {
+        TelloProxyBuilder::default()
+    }
+
+    /// The bound client ports, with the ones picked for port 0 filled in.
+    pub fn listen_addrs(&self) -> &ListenConfig {
+        &self.listen
+    }
+
+    /// The drone addresses, with the bound state and video ports.
+    pub fn tello_addrs(&self) -> &TelloConfig {
+        &self.tello
+    }
+
+    pub fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+
+    pub fn video_subscribers(&self) -> &VideoSubscribers {
+        &self.subscribers
+    }
+
+    pub fn safety(&self) -> &Safety {
+        &self.safety
+    }
+
+    pub fn flight(&self) -> &Flight {
+        &self.flight
+    }
+
+    pub fn geofence(&self) -> &Geofence {
+        &self.geofence
+    }
+
+    pub fn subscribe_states(&self) -> broadcast::Receiver<StateFrame> {
+        self.state_tx.subscribe()
+    }
+
+    /// Sends `cmd` through the dispatcher, queued with the clients' commands.
+    pub async fn send(&self, cmd: Command) -> Result<Option<String>, DispatchError> {
+        dispatch(&self.req_tx, cmd).await
+    }
+
+    /// The dispatcher's queue, for senders that live as long as the proxy
+    /// and are not command clients, like the console.
+    pub fn cmd_sender(&self) -> mpsc::Sender<CmdRequest> {
+        self.req_tx.clone()
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(self.stop_tx.clone())
+    }
+
+    /// Runs until stopped through a [`StopHandle`] or until a relay fails,
+    /// then shuts down.
+    pub async fn run(mut self) -> Result<(), ProxyError> {
+        let res = loop {
+            select! {
+                _ = self.stop_rx.wait_for(|stop| *stop) => break Ok(()),
+                res = self.tasks.join_next() => match res {
+                    Some(Ok(Ok(()))) => continue,
+                    Some(Ok(Err(e))) => break Err(ProxyError::Task(e)),
+                    Some(Err(e)) => break Err(ProxyError::Task(e.to_string())),
+                    None => break Ok(()),
+                },
+            }
+        };
+
+        self.shutdown().await?;
+        res
+    }
+
+    pub async fn stop(mut self) -> Result<(), ProxyError> {
+        self.shutdown().await
+    }
+
+    async fn shutdown(&mut self) -> Result<(), ProxyError> {
+        // closes every port and client connection
+        self.tasks.shutdown().await;
+
+        // flush what has been recorded so far
+        if self.recorder.session_dir().is_some() {
+            self.recorder.stop()?;
+        }
+
+        info!("proxy: Stopped");
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+//! Video relay: frames from the drone are fanned out to the subscribers over
+//! UDP, and subscribers can be added at runtime on a control port.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use log::info;
+use serde_json::{json, Value};
+use tokio::{
+    net::{TcpListener, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    control::listen_control,
+    h264::{AccessUnitAssembler, StreamCache, VIDEO_PACKET_SIZE},
+    recorder::Recorder,
+};
+
+/// Longest lifetime a subscriber can be registered for.
+pub const MAX_SUBSCRIBER_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Where the video is relayed to. Entries registered at runtime expire
+/// unless renewed; the ones from the config never do.
+#[derive(Debug, Clone, Default)]
+pub struct VideoSubscribers(Arc<Mutex<HashMap<SocketAddr, Option<Instant>>>>);
+
+impl VideoSubscribers {
+    pub fn new(static_targets: &[SocketAddr]) -> Self {
+        let map = static_targets.iter().map(|addr| (*addr, None)).collect();
+        Self(Arc::new(Mutex::new(map)))
+    }
+
+    /// Adds `addr` or renews its lifetime. Returns `false` if `ttl` is longer
+    /// than [`MAX_SUBSCRIBER_TTL`].
+    pub fn subscribe(&self, addr: SocketAddr, ttl: Duration) -> bool {
+        let expires_at = match Instant::now().checked_add(ttl) {
+            Some(t) if ttl <= MAX_SUBSCRIBER_TTL => t,
+            _ => return false,
+        };
+
+        let mut map = self.0.lock().unwrap();
+
+        match map.get_mut(&addr) {
+            // static entries stay static
+            Some(None) => (),
+            Some(Some(t)) => *t = expires_at,
+            None => {
+                map.insert(addr, Some(expires_at));
+            }
+        }
+        true
+    }
+
+    pub fn unsubscribe(&self, addr: SocketAddr) -> bool {
+        self.0.lock().unwrap().remove(&addr).is_some()
+    }
+
+    /// Current subscribers with the remaining lifetime, `None` for static ones.
+    pub fn list(&self) -> Vec<(SocketAddr, Option<Duration>)> {
+        let now = Instant::now();
+        let mut map = self.0.lock().unwrap();
+        map.retain(|_, expires_at| expires_at.is_none_or(|t| t > now));
+
+        map.iter()
+            .map(|(addr, expires_at)| (*addr, expires_at.map(|t| t - now)))
+            .collect()
+    }
+
+    pub fn targets(&self) -> Vec<SocketAddr> {
+        self.list().into_iter().map(|(addr, _)| addr).collect()
+    }
+}
+
+/// Relays the video frame by frame. A subscriber gets nothing until a
+/// keyframe has been seen; then the cached SPS/PPS, keyframe and the frames
+/// since come first so it can decode from the start.
+pub async fn listen_and_stream_video(
+    socket: UdpSocket,
+    doorbell_target: SocketAddr,
+    subscribers: VideoSubscribers,
+    recorder: Recorder,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut buf = vec![0; VIDEO_PACKET_SIZE];
+    let mut assembler = AccessUnitAssembler::new();
+    let mut cache = StreamCache::new();
+    let mut primed = HashSet::new();
+
+    socket.send_to(b"", doorbell_target).await?;
+
+    loop {
+        let size = match socket.recv_from(&mut buf).await {
+            Ok((size, _)) => size,
+            Err(_e) => {
+                //error!("Error while listening video: {:?}", e);
+                continue;
+            }
+        };
+
+        for access_unit in assembler.push(&buf[..size]) {
+            cache.update(&access_unit);
+            recorder.record_video(&access_unit, &cache);
+            let data = access_unit.to_annex_b();
+
+            let targets = subscribers.targets();
+            // forget expired subscribers, so they are primed again if they come back
+            primed.retain(|addr| targets.contains(addr));
+
+            for target in targets {
+                if primed.contains(&target) {
+                    send_video(&socket, &data, target).await;
+                } else if let Some(data) = cache.primer() {
+                    info!("listen video: Prime {} with keyframe", target);
+                    send_video(&socket, &data, target).await;
+                    primed.insert(target);
+                }
+            }
+        }
+    }
+}
+
+// keep the packet size of the drone, so subscribers need no bigger buffers
+async fn send_video(socket: &UdpSocket, data: &[u8], target: SocketAddr) {
+    for chunk in data.chunks(VIDEO_PACKET_SIZE) {
+        // ignore errors
+        let _ = socket.send_to(chunk, target).await;
+    }
+}
+
+/// Video subscriber control, one request per line:
+/// `subscribe <addr> [ttl_s]`, `unsubscribe <addr>` and `list`.
+pub async fn listen_video_control(
+    listener: TcpListener,
+    subscribers: VideoSubscribers,
+    default_ttl: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    listen_control(listener, "video control", move |line| {
+        control_video(&subscribers, default_ttl, line)
+    })
+    .await
+}
+
+fn control_video(subscribers: &VideoSubscribers, default_ttl: Duration, line: &str) -> String {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    match parts.as_slice() {
+        ["subscribe", addr] | ["subscribe", addr, _] => {
+            let addr: SocketAddr = match addr.parse() {
+                Ok(addr) => addr,
+                Err(e) => return format!("error invalid address \"{}\": {}", addr, e),
+            };
+
+            let ttl = match parts.get(2) {
+                Some(ttl) => match ttl.parse() {
+                    Ok(ttl) => Duration::from_secs(ttl),
+                    Err(_) => return format!("error invalid ttl \"{}\"", ttl),
+                },
+                None => default_ttl,
+            };
+
+            if !subscribers.subscribe(addr, ttl) {
+                return format!(
+                    "error ttl {}s is longer than {}s",
+                    ttl.as_secs(),
+                    MAX_SUBSCRIBER_TTL.as_secs()
+                );
+            }
+            info!(
+                "listen video control: Subscribe {} for {}s",
+                addr,
+                ttl.as_secs()
+            );
+            "ok".to_string()
+        }
+        ["unsubscribe", addr] => {
+            let addr: SocketAddr = match addr.parse() {
+                Ok(addr) => addr,
+                Err(e) => return format!("error invalid address \"{}\": {}", addr, e),
+            };
+
+            if subscribers.unsubscribe(addr) {
+                info!("listen video control: Unsubscribe {}", addr);
+                "ok".to_string()
+            } else {
+                format!("error {} is not subscribed", addr)
+            }
+        }
+        ["list"] => {
+            let list: Vec<Value> = subscribers
+                .list()
+                .into_iter()
+                .map(|(addr, ttl)| {
+                    json!({
+                        "addr": addr,
+                        "expires_in_ms": ttl.map(|ttl| ttl.as_millis() as u64),
+                    })
+                })
+                .collect();
+
+            Value::Array(list).to_string()
+        }
+        _ => format!("error unknown request \"{}\"", line),
+    }
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use common::*;
+use tello_autopilot::{
+    client::{ClientError, ClientOptions, RetryPolicy, TelloClient, Transport},
+    cmd::{Command, CommandResult},
+};
+use tokio::time::{Duration, Instant};
+
+fn options() -> ClientOptions {
+    ClientOptions {
+        timeout: Duration::from_millis(200),
+        motion_timeout: Duration::from_millis(500),
+        retry: RetryPolicy {
+            retries: 2,
+            delay: Duration::from_millis(10),
+        },
+    }
+}
+
+async fn udp_client(drone: &FakeDrone) -> TelloClient {
+    TelloClient::connect_with(Transport::Udp(drone.addr), options())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn sends_typed_commands() {
+    let mut drone = fake_drone(tello_replies).await;
+    let client = udp_client(&drone).await;
+
+    client.command().await.unwrap();
+    client.forward(50).await.unwrap();
+    client.cw(90).await.unwrap();
+    assert_eq!(client.battery().await.unwrap(), 87);
+    assert_eq!(client.speed().await.unwrap(), 100.0);
+
+    for cmd in ["command", "forward 50", "cw 90", "battery?", "speed?"] {
+        assert_eq!(drone.next_command().await, cmd);
+    }
+}
+
+#[tokio::test]
+async fn checks_ranges_before_sending() {
+    let mut drone = fake_drone(tello_replies).await;
+    let client = udp_client(&drone).await;
+
+    assert!(matches!(
+        client.forward(5000).await,
+        Err(ClientError::OutOfRange(_))
+    ));
+    assert!(matches!(
+        client.rc(0, 100, 0, 0).await,
+        Err(ClientError::OutOfRange(_))
+    ));
+    client.land().await.unwrap();
+
+    assert_eq!(drone.next_command().await, "land");
+}
+
+#[tokio::test]
+async fn reports_drone_errors() {
+    let drone = fake_drone(|cmd| match cmd {
+        "takeoff" => Reply::Now("error Motor stop"),
+        "battery?" => Reply::Now("ok"),
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let client = udp_client(&drone).await;
+
+    match client.takeoff().await {
+        Err(ClientError::Drone(res)) => assert_eq!(res, "error Motor stop"),
+        res => panic!("{:?}", res),
+    }
+    assert!(matches!(
+        client.battery().await,
+        Err(ClientError::Unexpected(_))
+    ));
+}
+
+#[tokio::test]
+async fn retries_queries_but_not_moves() {
+    let battery_reads = Arc::new(AtomicUsize::new(0));
+    let reads = battery_reads.clone();
+    let mut drone = fake_drone(move |cmd| match cmd {
+        // the first two are lost
+        "battery?" if reads.fetch_add(1, Ordering::SeqCst) < 2 => Reply::Never,
+        "forward 50" => Reply::Never,
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let client = udp_client(&drone).await;
+
+    assert_eq!(client.battery().await.unwrap(), 87);
+    assert_eq!(battery_reads.load(Ordering::SeqCst), 3);
+
+    let started = Instant::now();
+    assert!(matches!(
+        client.forward(50).await,
+        Err(ClientError::Timeout)
+    ));
+    // the motion timeout applies, once
+    assert!(started.elapsed() >= Duration::from_millis(500));
+
+    for _ in 0..3 {
+        assert_eq!(drone.next_command().await, "battery?");
+    }
+    assert_eq!(drone.next_command().await, "forward 50");
+    client.land().await.unwrap();
+    assert_eq!(drone.next_command().await, "land");
+}
+
+#[tokio::test]
+async fn serializes_concurrent_calls() {
+    let drone = fake_drone(|cmd| match cmd {
+        "speed?" => Reply::After(Duration::from_millis(50), "100.0"),
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let client = udp_client(&drone).await;
+
+    let (speed, battery, sdk) = tokio::join!(client.speed(), client.battery(), client.sdk());
+
+    assert_eq!(speed.unwrap(), 100.0);
+    assert_eq!(battery.unwrap(), 87);
+    assert_eq!(sdk.unwrap(), "30");
+}
+
+#[tokio::test]
+async fn works_through_the_proxy() {
+    let mut drone = fake_drone(|cmd| match cmd {
+        "wifi?" => Reply::Never,
+        cmd if cmd.starts_with("rc ") => Reply::Never,
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let proxy = start_proxy(drone.addr, Duration::from_millis(100)).await;
+    let client = TelloClient::connect_with(
+        Transport::Proxy(proxy.cmd),
+        ClientOptions {
+            // the proxy holds the queue for a while after rc
+            timeout: Duration::from_secs(1),
+            ..options()
+        },
+    )
+    .await
+    .unwrap();
+
+    client.takeoff().await.unwrap();
+    client.rc(0, 10, 0, 0).await.unwrap();
+    assert_eq!(client.battery().await.unwrap(), 87);
+    assert!(matches!(client.wifi().await, Err(ClientError::Timeout)));
+    assert_eq!(
+        client.send(Command::ReadSdk).await.unwrap(),
+        CommandResult::Sdk("30".to_string())
+    );
+
+    for cmd in ["takeoff", "rc 0 10 0 0", "battery?"] {
+        assert_eq!(drone.next_command().await, cmd);
+    }
+    // sent again after the proxy timed out
+    for _ in 0..3 {
+        assert_eq!(drone.next_command().await, "wifi?");
+    }
+}
+
+#[tokio::test]
+async fn keeps_proxy_responses_in_step_after_a_timeout() {
+    let drone = fake_drone(|cmd| match cmd {
+        // answered by the proxy after the client gave up
+        "speed?" => Reply::After(Duration::from_millis(300), "100.0"),
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let proxy = start_proxy(drone.addr, Duration::from_secs(1)).await;
+    let client = TelloClient::connect_with(
+        Transport::Proxy(proxy.cmd),
+        ClientOptions {
+            retry: RetryPolicy {
+                retries: 0,
+                delay: Duration::ZERO,
+            },
+            ..options()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(client.speed().await, Err(ClientError::Timeout)));
+    assert_eq!(client.battery().await.unwrap(), 87);
+}
@@ -0,0 +1,416 @@
+use tello_autopilot::cmd::{
+    Command, CommandParseError, CommandResult, Distance, ExtCommand, MatrixColor, MatrixGlyph,
+    MatrixPattern, MatrixText, Ssid, WifiPassword,
+};
+
+/// Parses `s`, and checks that the command is written back the same way and
+/// survives serde.
+fn round_trip(s: &str) {
+    let cmd: Command = s.parse().unwrap_or_else(|e| panic!("{}: {}", s, e));
+    assert_eq!(cmd.to_string(), s);
+    assert_eq!(cmd.to_string().parse::<Command>(), Ok(cmd.clone()));
+
+    let json = serde_json::to_string(&cmd).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Command>(&json).unwrap(),
+        cmd,
+        "{}",
+        json
+    );
+}
+
+fn out_of_range(command: &str, value: f64, min: f64, max: f64) -> CommandParseError {
+    CommandParseError::OutOfRange {
+        command: command.to_string(),
+        value,
+        min,
+        max,
+    }
+}
+
+fn invalid(command: &str, arg: &str) -> CommandParseError {
+    CommandParseError::InvalidArgument {
+        command: command.to_string(),
+        arg: arg.to_string(),
+    }
+}
+
+#[test]
+fn round_trips_sdk_2_commands() {
+    for s in [
+        "command",
+        "takeoff",
+        "land",
+        "streamon",
+        "streamoff",
+        "emergency",
+        "stop",
+        "up 20",
+        "down 500",
+        "left 20",
+        "right 500",
+        "forward 100",
+        "back 20",
+        "cw 1",
+        "ccw 360",
+        "flip l",
+        "flip r",
+        "flip f",
+        "flip b",
+        "go -500 500 0 10",
+        "go 20 -20 0 100 m1",
+        "curve 20 20 0 40 -500 500 10",
+        "curve 20 20 0 40 60 0 60 m8",
+        "jump 100 0 50 100 0 m1 m2",
+        "jump 0 0 0 10 360 m8 m7",
+        "speed 10",
+        "speed 100",
+        "rc -99 0 0 99",
+        "wifi tello-lab s3cret-pass",
+        "mon",
+        "moff",
+        "mdirection 0",
+        "mdirection 1",
+        "mdirection 2",
+        "ap tello-lab s3cret-pass",
+        "speed?",
+        "battery?",
+        "time?",
+        "wifi?",
+        "sdk?",
+        "sn?",
+    ] {
+        round_trip(s);
+    }
+}
+
+#[test]
+fn round_trips_sdk_3_commands() {
+    for s in [
+        "motoron",
+        "motoroff",
+        "throwfly",
+        "downvision 0",
+        "downvision 1",
+        "setfps low",
+        "setfps middle",
+        "setfps high",
+        "setbitrate 0",
+        "setbitrate 5",
+        "setresolution low",
+        "setresolution high",
+        "port 1025 65535",
+        "multwifi tello-lab s3cret-pass",
+    ] {
+        round_trip(s);
+    }
+}
+
+#[test]
+fn round_trips_ext_commands() {
+    for s in [
+        "EXT led 255 0 0",
+        "EXT led br 0.1 0 255 0",
+        "EXT led bl 2.5 255 0 0 0 0 255",
+        "EXT mled g rbp0rbp0",
+        "EXT mled l r 1.5 hello",
+        "EXT mled r b 0.1 hello",
+        "EXT mled u p 2.5 hello",
+        "EXT mled d r 1 hello",
+        "EXT mled s r A",
+        "EXT mled s p 7",
+        "EXT mled sc",
+        "EXT mled sl 255",
+        "EXT tof?",
+    ] {
+        round_trip(s);
+    }
+
+    assert_eq!(
+        Command::Ext(ExtCommand::MatrixClear).to_string(),
+        "EXT mled sc"
+    );
+    assert_eq!(Command::Up(Distance::new(50).unwrap()).to_string(), "up 50");
+}
+
+#[test]
+fn rejects_values_out_of_range() {
+    for (s, expected) in [
+        ("up 19", out_of_range("up", 19.0, 20.0, 500.0)),
+        ("back 501", out_of_range("back", 501.0, 20.0, 500.0)),
+        ("cw 0", out_of_range("cw", 0.0, 1.0, 360.0)),
+        ("ccw 361", out_of_range("ccw", 361.0, 1.0, 360.0)),
+        ("go -501 0 0 10", out_of_range("go", -501.0, -500.0, 500.0)),
+        ("go 0 0 501 10", out_of_range("go", 501.0, -500.0, 500.0)),
+        ("go 20 0 0 9", out_of_range("go", 9.0, 10.0, 100.0)),
+        ("go 20 0 0 101", out_of_range("go", 101.0, 10.0, 100.0)),
+        ("go 20 0 0 50 m0", out_of_range("go", 0.0, 1.0, 8.0)),
+        ("go 20 0 0 50 m9", out_of_range("go", 9.0, 1.0, 8.0)),
+        (
+            "curve 20 20 0 40 60 0 61",
+            out_of_range("curve", 61.0, 10.0, 60.0),
+        ),
+        (
+            "jump 0 0 0 10 361 m1 m2",
+            out_of_range("jump", 361.0, 0.0, 360.0),
+        ),
+        ("speed 9", out_of_range("speed", 9.0, 10.0, 100.0)),
+        ("rc -100 0 0 0", out_of_range("rc", -100.0, -99.0, 99.0)),
+        ("rc 0 0 0 100", out_of_range("rc", 100.0, -99.0, 99.0)),
+        ("setbitrate 6", out_of_range("setbitrate", 6.0, 0.0, 5.0)),
+        (
+            "port 1024 11111",
+            out_of_range("port", 1024.0, 1025.0, 65535.0),
+        ),
+        (
+            "port 8890 65536",
+            out_of_range("port", 65536.0, 1025.0, 65535.0),
+        ),
+        (
+            "EXT led 256 0 0",
+            out_of_range("EXT led", 256.0, 0.0, 255.0),
+        ),
+        ("EXT led 0 -1 0", out_of_range("EXT led", -1.0, 0.0, 255.0)),
+        (
+            "EXT led br 0.09 0 0 0",
+            out_of_range("EXT led", 0.09, 0.1, 2.5),
+        ),
+        (
+            "EXT mled l r 2.6 hello",
+            out_of_range("EXT mled", 2.6, 0.1, 2.5),
+        ),
+        (
+            "EXT mled sl 256",
+            out_of_range("EXT mled", 256.0, 0.0, 255.0),
+        ),
+    ] {
+        assert_eq!(s.parse::<Command>(), Err(expected), "{}", s);
+    }
+
+    assert_eq!(
+        "up 19".parse::<Command>().unwrap_err().to_string(),
+        "up: 19 is out of range, must be 20 ~ 500"
+    );
+    assert_eq!(
+        Distance::try_from(501).unwrap_err().to_string(),
+        "501 is out of range, must be 20 ~ 500"
+    );
+}
+
+#[test]
+fn reports_why_a_command_is_rejected() {
+    assert_eq!("".parse::<Command>(), Err(CommandParseError::Empty));
+    assert_eq!("  ".parse::<Command>(), Err(CommandParseError::Empty));
+    assert_eq!(
+        "fly 100".parse::<Command>(),
+        Err(CommandParseError::UnknownCommand("fly".to_string()))
+    );
+    assert_eq!(
+        "EXT fly".parse::<Command>(),
+        Err(CommandParseError::UnknownCommand("EXT fly".to_string()))
+    );
+
+    for (s, command, expected, found) in [
+        ("takeoff now", "takeoff", "0", 1),
+        ("up", "up", "1", 0),
+        ("go 1 2", "go", "4 or 5", 2),
+        ("curve 20 20 0 40 60 0", "curve", "7 or 8", 6),
+        ("jump 0 0 0 10 0 m1", "jump", "7", 6),
+        ("rc 0 0 0", "rc", "4", 3),
+        ("wifi tello-lab", "wifi", "2", 1),
+        ("EXT", "EXT", "1 ~ 9", 0),
+        ("EXT led 0 0", "EXT led", "3, 5 or 8", 2),
+        ("EXT tof? now", "EXT tof?", "0", 1),
+    ] {
+        assert_eq!(
+            s.parse::<Command>(),
+            Err(CommandParseError::WrongArity {
+                command: command.to_string(),
+                expected,
+                found,
+            }),
+            "{}",
+            s
+        );
+    }
+
+    for (s, command, arg) in [
+        ("up ten", "up", "ten"),
+        ("go 0 0 1.5 50", "go", "1.5"),
+        ("rc 0 0 0 x", "rc", "x"),
+        ("EXT led br fast 0 0 0", "EXT led", "fast"),
+    ] {
+        assert_eq!(
+            s.parse::<Command>(),
+            Err(CommandParseError::NotANumber {
+                command: command.to_string(),
+                arg: arg.to_string(),
+            }),
+            "{}",
+            s
+        );
+    }
+
+    for (s, expected) in [
+        ("flip x", invalid("flip", "x")),
+        ("go 20 0 0 50 1", invalid("go", "1")),
+        ("go 20 0 0 50 mx", invalid("go", "mx")),
+        ("mdirection 3", invalid("mdirection", "3")),
+        ("downvision on", invalid("downvision", "on")),
+        ("setfps 30", invalid("setfps", "30")),
+        ("setresolution 720p", invalid("setresolution", "720p")),
+        ("EXT led xx 1 0 0 0", invalid("EXT led", "xx")),
+        ("EXT mled g rgb", invalid("EXT mled", "rgb")),
+        ("EXT mled l g 1 hi", invalid("EXT mled", "g")),
+        ("EXT mled x", invalid("EXT mled", "x")),
+    ] {
+        assert_eq!(s.parse::<Command>(), Err(expected), "{}", s);
+    }
+
+    assert_eq!(
+        "up ten".parse::<Command>().unwrap_err().to_string(),
+        "up: \"ten\" is not a number"
+    );
+    assert_eq!(
+        "go 1 2".parse::<Command>().unwrap_err().to_string(),
+        "go: expected 4 or 5 argument(s), found 2"
+    );
+    assert_eq!(
+        "flip x".parse::<Command>().unwrap_err().to_string(),
+        "flip: invalid argument \"x\""
+    );
+}
+
+#[test]
+fn validates_wifi_credentials() {
+    assert_eq!(
+        "wifi tello-lab s3cret-pass".parse::<Command>(),
+        Ok(Command::Wifi {
+            ssid: Ssid::new("tello-lab").unwrap(),
+            pass: WifiPassword::new("s3cret-pass").unwrap(),
+        })
+    );
+
+    assert!(Ssid::try_from("a").is_ok());
+    assert!(Ssid::try_from("a".repeat(32)).is_ok());
+    assert!(Ssid::try_from("").is_err());
+    assert!(Ssid::try_from("a".repeat(33)).is_err());
+    assert!(Ssid::try_from("lab\twifi").is_err());
+    assert!(WifiPassword::try_from("12345678").is_ok());
+    assert!(WifiPassword::try_from("1".repeat(63)).is_ok());
+    assert!(WifiPassword::try_from("1234567").is_err());
+    assert!(WifiPassword::try_from("1".repeat(64)).is_err());
+    assert!(WifiPassword::try_from("pässwort").is_err());
+    assert_eq!(
+        WifiPassword::new("short").unwrap_err().to_string(),
+        "\"short\" is invalid, must be 8 ~ 63 printable ASCII characters without spaces"
+    );
+
+    assert_eq!(
+        "ap tello-lab short".parse::<Command>(),
+        Err(CommandParseError::InvalidArgument {
+            command: "ap".to_string(),
+            arg: "short".to_string(),
+        })
+    );
+    let long = format!("multwifi {} s3cret-pass", "a".repeat(33));
+    assert!(matches!(
+        long.parse::<Command>(),
+        Err(CommandParseError::InvalidArgument { .. })
+    ));
+
+    // serde checks them too
+    assert_eq!(
+        serde_json::to_string(&Ssid::new("tello-lab").unwrap()).unwrap(),
+        "\"tello-lab\""
+    );
+    assert!(serde_json::from_str::<Ssid>("\"tello lab\"").is_err());
+    assert!(serde_json::from_str::<Command>(
+        r#"{"access_point":{"ssid":"tello-lab","pass":"short"}}"#
+    )
+    .is_err());
+}
+
+#[test]
+fn validates_matrix_glyphs() {
+    assert_eq!(
+        "EXT mled s r A".parse::<Command>(),
+        Ok(Command::Ext(ExtCommand::MatrixChar {
+            color: MatrixColor::Red,
+            ch: MatrixGlyph::new('A').unwrap(),
+        }))
+    );
+
+    assert!(MatrixGlyph::try_from('7').is_ok());
+    assert!(MatrixGlyph::try_from(' ').is_err());
+    assert!(MatrixGlyph::try_from('é').is_err());
+    for cmd in ["EXT mled s r AB", "EXT mled s r é", "EXT mled s r %"] {
+        assert!(
+            matches!(
+                cmd.parse::<Command>(),
+                Err(CommandParseError::InvalidArgument { .. })
+            ),
+            "{}",
+            cmd
+        );
+    }
+
+    assert!(serde_json::from_str::<MatrixGlyph>("\"x\"").is_ok());
+    assert!(serde_json::from_str::<MatrixGlyph>("\" \"").is_err());
+}
+
+#[test]
+fn validates_matrix_patterns_and_text() {
+    assert!(MatrixPattern::new("rbp0").is_ok());
+    assert!(MatrixPattern::new(&"r".repeat(64)).is_ok());
+    assert_eq!(
+        MatrixPattern::new("rx").unwrap_err().to_string(),
+        "\"rx\" is invalid, must be 1 to 64 of r, b, p or 0"
+    );
+    assert!(MatrixPattern::new("").is_err());
+    assert!(MatrixPattern::new(&"r".repeat(65)).is_err());
+
+    assert!(MatrixText::new("héllo!").is_ok());
+    assert!(MatrixText::new(&"a".repeat(70)).is_ok());
+    assert_eq!(
+        MatrixText::new("a b").unwrap_err().to_string(),
+        "\"a b\" is invalid, must be 1 to 70 characters without whitespace"
+    );
+    assert!(MatrixText::new(&"a".repeat(71)).is_err());
+
+    assert!(serde_json::from_str::<MatrixPattern>("\"rx\"").is_err());
+    assert!(serde_json::from_str::<MatrixText>("\"\"").is_err());
+}
+
+#[test]
+fn reads_query_responses() {
+    assert_eq!(
+        CommandResult::from_response(&Command::ReadSdk, "30\r\n"),
+        CommandResult::Sdk("30".to_string())
+    );
+    assert_eq!(
+        CommandResult::from_response(&Command::ReadSerialNumber, "0TQZH77ED00H5K"),
+        CommandResult::SerialNumber("0TQZH77ED00H5K".to_string())
+    );
+    assert_eq!(
+        CommandResult::from_response(&Command::ReadBattery, "87"),
+        CommandResult::Battery(87)
+    );
+
+    // errors and stray answers are not taken as the value
+    for cmd in [Command::ReadSdk, Command::ReadSerialNumber] {
+        assert_eq!(
+            CommandResult::from_response(&cmd, "error Motor stop"),
+            CommandResult::Error
+        );
+        assert_eq!(
+            CommandResult::from_response(&cmd, "error"),
+            CommandResult::Error
+        );
+        assert_eq!(CommandResult::from_response(&cmd, "ok"), CommandResult::Ok);
+    }
+    assert_eq!(
+        CommandResult::from_response(&Command::ReadBattery, "error Not joystick"),
+        CommandResult::Error
+    );
+}
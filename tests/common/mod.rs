@@ -0,0 +1,372 @@
+//! Loopback fixtures: a scripted fake drone and the relays bound to
+//! ephemeral ports.
+
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+
+use tello_autopilot::{
+    config::{FlightConfig, GeofenceConfig, ListenConfig, SafetyConfig, TelloConfig},
+    flight::Flight,
+    geofence::Geofence,
+    proxy::{dispatch_cmd, listen_and_send_cmd, listen_and_send_rpc, CmdGuards, Framing},
+    recorder::Recorder,
+    relay::{listen_and_send_state, recv_state, STATE_CHANNEL_CAPACITY},
+    safety::Safety,
+    video::{listen_and_stream_video, listen_video_control, VideoSubscribers},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream, UdpSocket,
+    },
+    spawn,
+    sync::{broadcast, mpsc},
+    time::{sleep, timeout, Duration},
+};
+
+/// How long a test waits for something that should arrive, long enough for
+/// a simulated takeoff
+pub const WAIT: Duration = Duration::from_secs(10);
+
+/// How the fake drone answers a command.
+pub enum Reply {
+    Now(&'static str),
+    After(Duration, &'static str),
+    Never,
+}
+
+pub struct FakeDrone {
+    pub addr: SocketAddr,
+    /// Every command received, in order
+    pub received: mpsc::UnboundedReceiver<String>,
+}
+
+impl FakeDrone {
+    pub async fn next_command(&mut self) -> String {
+        timeout(WAIT, self.received.recv())
+            .await
+            .expect("drone received no command")
+            .unwrap()
+    }
+}
+
+pub async fn fake_drone<F>(respond: F) -> FakeDrone
+where
+    F: Fn(&str) -> Reply + Send + 'static,
+{
+    let socket = std::sync::Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let addr = socket.local_addr().unwrap();
+    let (received_tx, received) = mpsc::unbounded_channel();
+
+    spawn(async move {
+        let mut buf = vec![0; 1024];
+        while let Ok((size, from)) = socket.recv_from(&mut buf).await {
+            let cmd = String::from_utf8_lossy(&buf[..size]).to_string();
+            let reply = respond(&cmd);
+            let _ = received_tx.send(cmd);
+
+            match reply {
+                Reply::Now(res) => {
+                    let _ = socket.send_to(res.as_bytes(), from).await;
+                }
+                Reply::After(delay, res) => {
+                    let socket = socket.clone();
+                    spawn(async move {
+                        sleep(delay).await;
+                        let _ = socket.send_to(res.as_bytes(), from).await;
+                    });
+                }
+                Reply::Never => (),
+            }
+        }
+    });
+
+    FakeDrone { addr, received }
+}
+
+/// Answers like a drone that is happy with everything.
+pub fn tello_replies(cmd: &str) -> Reply {
+    match cmd {
+        "battery?" => Reply::Now("87\r\n"),
+        "sdk?" => Reply::Now("30\r\n"),
+        "speed?" => Reply::Now("100.0\r\n"),
+        _ => Reply::Now("ok"),
+    }
+}
+
+pub struct Proxy {
+    pub cmd: SocketAddr,
+    pub legacy_cmd: SocketAddr,
+    pub rpc: SocketAddr,
+}
+
+pub async fn start_proxy(drone: SocketAddr, res_timeout: Duration) -> Proxy {
+    start_proxy_with_keepalive(drone, res_timeout, None).await
+}
+
+pub async fn start_proxy_with_keepalive(
+    drone: SocketAddr,
+    res_timeout: Duration,
+    keepalive: Option<Duration>,
+) -> Proxy {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let (req_tx, req_rx) = mpsc::channel(32);
+    // there are no states, so no failsafe trips; the flight status and the
+    // geofence are not enforced
+    let flight = Flight::new(FlightConfig {
+        enforce: false,
+        ..FlightConfig::default()
+    });
+    let safety = Safety::new(SafetyConfig::default(), flight.clone());
+    let guards = CmdGuards {
+        safety: safety.clone(),
+        flight: flight.clone(),
+        geofence: Geofence::new(GeofenceConfig::default(), flight),
+    };
+    spawn(dispatch_cmd(
+        socket,
+        drone,
+        res_timeout,
+        keepalive,
+        req_rx,
+        Recorder::new(std::env::temp_dir()),
+        guards,
+    ));
+
+    let (cmd_listener, cmd) = bind_tcp().await;
+    let line_req_tx = req_tx.clone();
+    let line_safety = safety.clone();
+    spawn(async move {
+        let _ = listen_and_send_cmd(cmd_listener, Framing::Line, line_req_tx, line_safety).await;
+    });
+
+    let (legacy_listener, legacy_cmd) = bind_tcp().await;
+    let legacy_req_tx = req_tx.clone();
+    let legacy_safety = safety.clone();
+    spawn(async move {
+        let _ = listen_and_send_cmd(
+            legacy_listener,
+            Framing::Legacy,
+            legacy_req_tx,
+            legacy_safety,
+        )
+        .await;
+    });
+
+    let (rpc_listener, rpc) = bind_tcp().await;
+    spawn(async move {
+        let _ = listen_and_send_rpc(rpc_listener, req_tx, safety).await;
+    });
+
+    Proxy {
+        cmd,
+        legacy_cmd,
+        rpc,
+    }
+}
+
+pub struct StateRelay {
+    /// Where the drone sends states to
+    pub udp: SocketAddr,
+    pub tcp: SocketAddr,
+}
+
+pub async fn start_state_relay() -> StateRelay {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let udp = socket.local_addr().unwrap();
+    let doorbell = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let doorbell_addr = doorbell.local_addr().unwrap();
+    let (state_tx, _) = broadcast::channel(STATE_CHANNEL_CAPACITY);
+
+    let recv_state_tx = state_tx.clone();
+    spawn(async move {
+        // keep the doorbell open, so the relay socket gets no ICMP errors
+        let _doorbell = doorbell;
+        let _ = recv_state(
+            socket,
+            doorbell_addr,
+            Duration::from_secs(60),
+            recv_state_tx,
+            Recorder::new(std::env::temp_dir()),
+            Flight::new(FlightConfig::default()),
+        )
+        .await;
+    });
+
+    let (listener, tcp) = bind_tcp().await;
+    spawn(async move {
+        let _ = listen_and_send_state(listener, state_tx).await;
+    });
+
+    StateRelay { udp, tcp }
+}
+
+pub struct VideoRelay {
+    /// Where the drone sends video to
+    pub udp: SocketAddr,
+    pub control: SocketAddr,
+}
+
+pub async fn start_video_relay(static_subscribers: &[SocketAddr]) -> VideoRelay {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let udp = socket.local_addr().unwrap();
+    let doorbell = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let doorbell_addr = doorbell.local_addr().unwrap();
+    let subscribers = VideoSubscribers::new(static_subscribers);
+
+    let stream_subscribers = subscribers.clone();
+    spawn(async move {
+        let _doorbell = doorbell;
+        let _ = listen_and_stream_video(
+            socket,
+            doorbell_addr,
+            stream_subscribers,
+            Recorder::new(std::env::temp_dir()),
+        )
+        .await;
+    });
+
+    let (listener, control) = bind_tcp().await;
+    spawn(async move {
+        let _ = listen_video_control(listener, subscribers, Duration::from_secs(30)).await;
+    });
+
+    VideoRelay { udp, control }
+}
+
+/// Client ports on loopback, picked by the system.
+pub fn loopback_listen() -> ListenConfig {
+    let any = ([127, 0, 0, 1], 0).into();
+    ListenConfig {
+        cmd: any,
+        legacy_cmd: any,
+        state: any,
+        rpc: any,
+        video_control: any,
+        record_control: any,
+        events: any,
+    }
+}
+
+/// `drone` for commands and doorbells, state and video ports picked by the system.
+pub fn loopback_tello(drone: SocketAddr) -> TelloConfig {
+    TelloConfig {
+        cmd: drone,
+        state: ([127, 0, 0, 1], 0).into(),
+        video: ([127, 0, 0, 1], 0).into(),
+        video_doorbell: drone,
+    }
+}
+
+async fn bind_tcp() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener, addr)
+}
+
+/// A client of a line-based port.
+pub struct LineClient {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl LineClient {
+    pub async fn connect(addr: SocketAddr) -> Self {
+        let (reader, writer) = TcpStream::connect(addr).await.unwrap().into_split();
+        Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+
+    pub async fn send(&mut self, line: &str) {
+        self.writer
+            .write_all(format!("{}\n", line).as_bytes())
+            .await
+            .unwrap();
+    }
+
+    pub async fn recv(&mut self) -> String {
+        timeout(WAIT, self.lines.next_line())
+            .await
+            .expect("no response")
+            .unwrap()
+            .expect("connection closed")
+    }
+
+    pub async fn request(&mut self, line: &str) -> String {
+        self.send(line).await;
+        self.recv().await
+    }
+}
+
+/// Collects what arrives on `socket` until nothing has come for a while.
+pub async fn recv_until_quiet(socket: &UdpSocket) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    let mut buf = vec![0; 2048];
+
+    while let Ok(Ok(size)) = timeout(Duration::from_millis(300), socket.recv(&mut buf)).await {
+        packets.push(buf[..size].to_vec());
+    }
+    packets
+}
+
+/// A state line as the drone sends it.
+pub fn state_line(yaw: isize, battery: usize) -> String {
+    format!(
+        "pitch:0;roll:0;yaw:{};vgx:0;vgy:0;vgz:0;templ:83;temph:85;tof:10;h:0;bat:{};baro:152.82;time:0;agx:-2.00;agy:-3.00;agz:-999.00;\r\n",
+        yaw, battery
+    )
+}
+
+/// A state line with the values the failsafes look at.
+pub fn flight_state_line(height: usize, battery: usize, temp_high: usize) -> String {
+    format!(
+        "pitch:0;roll:0;yaw:0;vgx:0;vgy:0;vgz:0;templ:60;temph:{};tof:{};h:{};bat:{};baro:152.82;time:0;agx:0.00;agy:0.00;agz:-1000.00;\r\n",
+        temp_high,
+        height + 10,
+        height,
+        battery
+    )
+}
+
+/// NAL unit with a start code; the second byte marks the first slice of a picture.
+pub fn nal_unit(header: u8, tag: u8, len: usize) -> Vec<u8> {
+    let mut nal = vec![0, 0, 0, 1, header, 0x88, tag];
+    nal.extend((0..len).map(|i| (i % 250 + 1) as u8));
+    nal
+}
+
+/// `frames` frames with SPS, PPS and a keyframe every `gop` frames, tagged
+/// with the frame number.
+pub fn h264_stream(frames: usize, gop: usize) -> Vec<u8> {
+    let mut stream = Vec::new();
+    for i in 0..frames {
+        let tag = i as u8;
+        if i % gop == 0 {
+            stream.extend(nal_unit(0x67, tag, 10));
+            stream.extend(nal_unit(0x68, tag, 4));
+            stream.extend(nal_unit(0x65, tag, 3000));
+        } else {
+            stream.extend(nal_unit(0x41, tag, 1500));
+        }
+    }
+    stream
+}
+
+/// `(nal type, frame number)` of every NAL unit in an Annex-B stream.
+pub fn nal_units(stream: &[u8]) -> Vec<(u8, u8)> {
+    let mut units = Vec::new();
+    let mut i = 0;
+    while i + 6 < stream.len() {
+        if stream[i..i + 4] == [0, 0, 0, 1] {
+            units.push((stream[i + 4] & 0x1f, stream[i + 6]));
+            i += 4;
+        } else {
+            i += 1;
+        }
+    }
+    units
+}
@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use tello_autopilot::{
+    cli::Cli,
+    config::{Config, ConfigError},
+};
+
+fn temp_config(name: &str, toml: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("tello-config-{}-{}.toml", std::process::id(), name));
+    std::fs::write(&path, toml).unwrap();
+    path
+}
+
+#[test]
+fn parses_the_example_config() {
+    // it lists the defaults
+    assert_eq!(
+        Config::load("tello-autopilot.example.toml").unwrap(),
+        Config::default()
+    );
+
+    let config: Config = "res_timeout_ms = 1000\n[listen]\ncmd = \"0.0.0.0:7000\""
+        .parse()
+        .unwrap();
+    assert_eq!(config.res_timeout_ms, 1000);
+    assert_eq!(config.listen.cmd, "0.0.0.0:7000".parse().unwrap());
+    assert_eq!(config.listen.rpc, Config::default().listen.rpc);
+}
+
+#[test]
+fn rejects_unknown_keys() {
+    for toml in ["res_timeout = 1000", "[listen]\ncommand = \"0.0.0.0:7000\""] {
+        assert!(
+            matches!(toml.parse::<Config>(), Err(ConfigError::Parse(_))),
+            "{}",
+            toml
+        );
+    }
+}
+
+#[test]
+fn rejects_values_that_cannot_work() {
+    for toml in [
+        "res_timeout_ms = 0",
+        "[video]\nsubscriber_ttl_ms = 86400001",
+    ] {
+        assert!(
+            matches!(toml.parse::<Config>(), Err(ConfigError::Invalid(_))),
+            "{}",
+            toml
+        );
+    }
+    assert!("[video]\nsubscriber_ttl_ms = 86400000"
+        .parse::<Config>()
+        .is_ok());
+}
+
+#[test]
+fn cli_flags_override_the_file() {
+    let path = temp_config(
+        "override",
+        "log_level = \"debug\"\nres_timeout_ms = 1000\n[video]\nsubscribers = [\"127.0.0.1:5000\"]",
+    );
+    let parse = |args: &[&str]| {
+        let path = path.to_str().unwrap();
+        Cli::try_parse_from(["tello-autopilot", "--config", path].iter().chain(args))
+            .unwrap()
+            .into_config()
+    };
+
+    let config = parse(&[
+        "--res-timeout-ms",
+        "200",
+        "--video-subscriber",
+        "127.0.0.1:6000",
+        "--video-subscriber",
+        "127.0.0.1:6001",
+        "--record",
+    ])
+    .unwrap();
+    assert_eq!(config.log_level, "debug");
+    assert_eq!(config.res_timeout_ms, 200);
+    assert_eq!(
+        config.video.subscribers,
+        [
+            "127.0.0.1:6000".parse().unwrap(),
+            "127.0.0.1:6001".parse().unwrap()
+        ]
+    );
+    assert!(config.record.autostart);
+
+    // the file is kept where no flag is given
+    let config = parse(&[]).unwrap();
+    assert_eq!(config.res_timeout_ms, 1000);
+    assert_eq!(
+        config.video.subscribers,
+        ["127.0.0.1:5000".parse().unwrap()]
+    );
+
+    // and the result is checked again
+    assert!(matches!(
+        parse(&["--res-timeout-ms", "0"]),
+        Err(ConfigError::Invalid(_))
+    ));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::*;
+use tello_autopilot::{config::SafetyConfig, flight::FlightStatus, state::StateFrame, TelloProxy};
+use tokio::{
+    net::UdpSocket,
+    time::{sleep, timeout, Duration},
+};
+
+async fn start(drone: &FakeDrone) -> TelloProxy {
+    TelloProxy::builder()
+        .listen(loopback_listen())
+        .tello(loopback_tello(drone.addr))
+        .video_subscribers(Vec::new())
+        .res_timeout(Duration::from_millis(200))
+        .keepalive(None)
+        .handshake(false)
+        .record_dir(std::env::temp_dir())
+        // no landing when the states stop
+        .safety(SafetyConfig {
+            state_timeout_ms: 0,
+            ..SafetyConfig::default()
+        })
+        .start()
+        .await
+        .unwrap()
+}
+
+async fn send_state(proxy: &TelloProxy, height: usize, battery: usize) {
+    UdpSocket::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .send_to(
+            flight_state_line(height, battery, 60).as_bytes(),
+            proxy.tello_addrs().state,
+        )
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(50)).await;
+}
+
+/// The commands that reached the drone, skipping the relay doorbells.
+async fn sent(drone: &mut FakeDrone) -> Vec<String> {
+    let mut cmds = Vec::new();
+    while let Ok(Some(cmd)) = timeout(Duration::from_millis(100), drone.received.recv()).await {
+        if !cmd.is_empty() {
+            cmds.push(cmd);
+        }
+    }
+    cmds
+}
+
+#[tokio::test]
+async fn refuses_invalid_sequences() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start(&drone).await;
+    let mut client = LineClient::connect(proxy.listen_addrs().cmd).await;
+
+    assert_eq!(
+        client.request("forward 50").await,
+        "error refused: forward 50 while grounded"
+    );
+    assert_eq!(client.request("takeoff").await, "ok");
+    assert_eq!(proxy.flight().status(), FlightStatus::Airborne);
+    assert_eq!(
+        client.request("takeoff").await,
+        "error refused: takeoff while airborne"
+    );
+
+    send_state(&proxy, 100, 40).await;
+    assert_eq!(
+        client.request("flip l").await,
+        "error refused: flip l below 50% battery (40%)"
+    );
+    assert_eq!(client.request("forward 50").await, "ok");
+    assert_eq!(client.request("land").await, "ok");
+    assert_eq!(proxy.flight().status(), FlightStatus::Grounded);
+    // landing is never refused
+    assert_eq!(client.request("land").await, "ok");
+
+    assert_eq!(
+        sent(&mut drone).await,
+        ["takeoff", "forward 50", "land", "land"]
+    );
+}
+
+#[tokio::test]
+async fn follows_the_answers() {
+    let drone = fake_drone(|cmd| match cmd {
+        "takeoff" => Reply::After(Duration::from_millis(150), "ok"),
+        "land" => Reply::Now("error Not joystick"),
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let proxy = start(&drone).await;
+    let mut client = LineClient::connect(proxy.listen_addrs().cmd).await;
+
+    client.send("takeoff").await;
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(proxy.flight().status(), FlightStatus::TakingOff);
+    assert_eq!(client.recv().await, "ok");
+    assert_eq!(proxy.flight().status(), FlightStatus::Airborne);
+
+    // still in the air
+    assert_eq!(client.request("land").await, "error Not joystick");
+    assert_eq!(proxy.flight().status(), FlightStatus::Airborne);
+
+    assert_eq!(client.request("emergency").await, "ok");
+    assert_eq!(proxy.flight().status(), FlightStatus::Grounded);
+}
+
+#[tokio::test]
+async fn follows_the_states() {
+    let drone = fake_drone(tello_replies).await;
+    let proxy = start(&drone).await;
+    let mut states = LineClient::connect(proxy.listen_addrs().state).await;
+    sleep(Duration::from_millis(50)).await;
+
+    // thrown, or in the air before the proxy started
+    send_state(&proxy, 100, 80).await;
+    let frame: StateFrame = serde_json::from_str(&states.recv().await).unwrap();
+    assert_eq!(frame.flight, FlightStatus::Airborne);
+
+    // landed on its own
+    send_state(&proxy, 0, 80).await;
+    let frame: StateFrame = serde_json::from_str(&states.recv().await).unwrap();
+    assert_eq!(frame.flight, FlightStatus::Grounded);
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use common::*;
+use tello_autopilot::{config::GeofenceConfig, geofence::FenceArea, TelloProxy};
+use tokio::time::{timeout, Duration};
+
+fn lab() -> GeofenceConfig {
+    GeofenceConfig {
+        enabled: true,
+        ..GeofenceConfig::default()
+    }
+}
+
+async fn start(drone: &FakeDrone, geofence: GeofenceConfig) -> TelloProxy {
+    TelloProxy::builder()
+        .listen(loopback_listen())
+        .tello(loopback_tello(drone.addr))
+        .video_subscribers(Vec::new())
+        .res_timeout(Duration::from_millis(200))
+        .keepalive(None)
+        .handshake(false)
+        .record_dir(std::env::temp_dir())
+        .geofence(geofence)
+        .start()
+        .await
+        .unwrap()
+}
+
+/// The commands that reached the drone, skipping the relay doorbells.
+async fn sent(drone: &mut FakeDrone) -> Vec<String> {
+    let mut cmds = Vec::new();
+    while let Ok(Some(cmd)) = timeout(Duration::from_millis(100), drone.received.recv()).await {
+        if !cmd.is_empty() {
+            cmds.push(cmd);
+        }
+    }
+    cmds
+}
+
+#[tokio::test]
+async fn refuses_moves_leaving_the_box() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start(&drone, lab()).await;
+    let mut client = LineClient::connect(proxy.listen_addrs().cmd).await;
+
+    assert_eq!(client.request("takeoff").await, "ok");
+    assert_eq!(client.request("forward 200").await, "ok");
+    assert_eq!(
+        client.request("forward 100").await,
+        "error refused: forward 100 would leave the geofence: x outside -250 to 250 at (300, 0, 80)"
+    );
+    // facing right, towards -y
+    assert_eq!(client.request("cw 90").await, "ok");
+    assert_eq!(client.request("forward 100").await, "ok");
+    assert_eq!(
+        client.request("up 150").await,
+        "error refused: up 150 would leave the geofence: above the 200cm ceiling at (200, -100, 230)"
+    );
+    assert_eq!(
+        client.request("down 70").await,
+        "error refused: down 70 would leave the geofence: below the 20cm floor at (200, -100, 10)"
+    );
+    assert_eq!(client.request("land").await, "ok");
+
+    let position = proxy.geofence().position();
+    assert!((position.x - 200.0).abs() < 1e-6);
+    assert!((position.y + 100.0).abs() < 1e-6);
+    assert_eq!(position.z, 0.0);
+    assert_eq!(position.yaw, 90.0);
+    assert_eq!(
+        sent(&mut drone).await,
+        ["takeoff", "forward 200", "cw 90", "forward 100", "land"]
+    );
+}
+
+#[tokio::test]
+async fn checks_go_and_curve_in_a_cylinder() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start(
+        &drone,
+        GeofenceConfig {
+            area: FenceArea::Cylinder { radius_cm: 100.0 },
+            ..lab()
+        },
+    )
+    .await;
+    let mut client = LineClient::connect(proxy.listen_addrs().cmd).await;
+
+    assert_eq!(client.request("takeoff").await, "ok");
+    assert_eq!(client.request("go 80 0 0 50").await, "ok");
+    assert_eq!(
+        client.request("go 0 80 0 50").await,
+        "error refused: go 0 80 0 50 would leave the geofence: farther than 100cm at (80, 80, 80)"
+    );
+    // ends inside, but the arc does not
+    assert_eq!(
+        client.request("curve 20 -150 0 -40 -20 0 30").await,
+        "error refused: curve 20 -150 0 -40 -20 0 30 would leave the geofence: farther than 100cm at (100, -150, 80)"
+    );
+    assert!(client
+        .request("go 0 0 20 50 m1")
+        .await
+        .ends_with("is relative to a mission pad and cannot be checked against the geofence"));
+    assert_eq!(client.request("curve -20 20 0 -40 -20 0 30").await, "ok");
+
+    let position = proxy.geofence().position();
+    assert_eq!((position.x, position.y), (40.0, -20.0));
+    assert_eq!(
+        sent(&mut drone).await,
+        ["takeoff", "go 80 0 0 50", "curve -20 20 0 -40 -20 0 30"]
+    );
+}
+
+#[tokio::test]
+async fn tracks_only_executed_commands() {
+    let mut drone = fake_drone(|cmd| match cmd {
+        "forward 200" => Reply::Now("error No valid imu"),
+        "back 200" => Reply::Never,
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let proxy = start(&drone, lab()).await;
+    let mut client = LineClient::connect(proxy.listen_addrs().cmd).await;
+
+    // on the ground the flight status refuses moves first
+    assert_eq!(
+        client.request("down 100").await,
+        "error refused: down 100 while grounded"
+    );
+    assert_eq!(client.request("takeoff").await, "ok");
+    assert_eq!(client.request("forward 200").await, "error No valid imu");
+    assert_eq!(
+        client.request("back 200").await,
+        "error timed out waiting response"
+    );
+    // neither moved the estimate
+    assert_eq!(client.request("forward 250").await, "ok");
+    assert_eq!(proxy.geofence().position().x, 250.0);
+
+    assert_eq!(
+        sent(&mut drone).await,
+        ["takeoff", "forward 200", "back 200", "forward 250"]
+    );
+}
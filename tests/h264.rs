@@ -0,0 +1,186 @@
+use tello_autopilot::h264::{AccessUnit, AccessUnitAssembler, NalReader, NalUnit, StreamCache};
+
+const SPS: [u8; 4] = [0x67, 0x42, 0xc0, 0x1f];
+const PPS: [u8; 3] = [0x68, 0xce, 0x3c];
+// the second byte has the first bit set: `first_mb_in_slice` is 0
+const IDR: [u8; 4] = [0x65, 0x88, 0x84, 0x21];
+const IDR_PART: [u8; 4] = [0x65, 0x1a, 0x84, 0x21];
+const SLICE: [u8; 4] = [0x41, 0x9a, 0x02, 0x03];
+const SLICE_PART: [u8; 4] = [0x41, 0x2a, 0x02, 0x03];
+
+fn nal(data: &[u8]) -> NalUnit {
+    NalUnit::new(data.to_vec()).unwrap()
+}
+
+fn access_unit(nal_units: &[&[u8]]) -> AccessUnit {
+    AccessUnit {
+        nal_units: nal_units.iter().map(|data| nal(data)).collect(),
+    }
+}
+
+/// `nal_units` as an Annex-B stream with 4-byte start codes.
+fn annex_b(nal_units: &[&[u8]]) -> Vec<u8> {
+    nal_units
+        .iter()
+        .flat_map(|data| [&[0, 0, 0, 1], *data].concat())
+        .collect()
+}
+
+fn read_all(reader: &mut NalReader, chunks: &[&[u8]]) -> Vec<NalUnit> {
+    let mut nal_units: Vec<_> = chunks.iter().flat_map(|c| reader.push(c)).collect();
+    nal_units.extend(reader.finish());
+    nal_units
+}
+
+fn assemble_all(chunks: &[&[u8]]) -> Vec<AccessUnit> {
+    let mut assembler = AccessUnitAssembler::new();
+    let mut access_units: Vec<_> = chunks.iter().flat_map(|c| assembler.push(c)).collect();
+    access_units.extend(assembler.finish());
+    access_units
+}
+
+#[test]
+fn reads_start_codes_split_across_packets() {
+    let stream = annex_b(&[&SPS, &PPS, &IDR, &SLICE]);
+    let expected = [nal(&SPS), nal(&PPS), nal(&IDR), nal(&SLICE)];
+
+    for at in 0..=stream.len() {
+        let (a, b) = stream.split_at(at);
+        assert_eq!(
+            read_all(&mut NalReader::new(), &[a, b]),
+            expected,
+            "split at {}",
+            at
+        );
+    }
+
+    let bytes: Vec<&[u8]> = stream.chunks(1).collect();
+    assert_eq!(read_all(&mut NalReader::new(), &bytes), expected);
+}
+
+#[test]
+fn reads_3_and_4_byte_start_codes() {
+    let stream = [
+        &[0, 0, 1][..],
+        &SPS,
+        &[0, 0, 0, 1],
+        &PPS,
+        &[0, 0, 1],
+        &IDR,
+        &[0, 0, 0, 1],
+        &SLICE,
+    ]
+    .concat();
+
+    let mut reader = NalReader::new();
+    // the last unit is held back until the stream ends
+    assert_eq!(reader.push(&stream), [nal(&SPS), nal(&PPS), nal(&IDR)]);
+    assert_eq!(reader.finish(), Some(nal(&SLICE)));
+
+    // garbage before the first start code is dropped
+    let mut reader = NalReader::new();
+    assert_eq!(
+        read_all(&mut reader, &[&[0x12, 0x34, 0, 0, 1], &SPS]),
+        [nal(&SPS)]
+    );
+
+    // written back with 4-byte start codes
+    let access_units = assemble_all(&[&stream]);
+    assert_eq!(access_units.len(), 2);
+    assert_eq!(access_units[0].to_annex_b(), annex_b(&[&SPS, &PPS, &IDR]));
+    assert_eq!(access_units[1].to_annex_b(), annex_b(&[&SLICE]));
+}
+
+#[test]
+fn splits_access_units_on_the_first_slice_of_a_picture() {
+    let stream = annex_b(&[
+        &SPS,
+        &PPS,
+        &IDR,
+        &IDR_PART,
+        &SLICE,
+        &SLICE_PART,
+        &SLICE_PART,
+        &SLICE,
+    ]);
+
+    let expected = [
+        access_unit(&[&SPS, &PPS, &IDR, &IDR_PART]),
+        access_unit(&[&SLICE, &SLICE_PART, &SLICE_PART]),
+        access_unit(&[&SLICE]),
+    ];
+    assert_eq!(assemble_all(&[&stream]), expected);
+    assert!(expected[0].is_keyframe());
+    assert!(!expected[1].is_keyframe());
+
+    let packets: Vec<&[u8]> = stream.chunks(3).collect();
+    assert_eq!(assemble_all(&packets), expected);
+}
+
+#[test]
+fn completes_an_access_unit_once_the_next_header_arrives() {
+    let mut assembler = AccessUnitAssembler::new();
+
+    assert!(assembler.push(&annex_b(&[&SPS, &PPS, &IDR])).is_empty());
+    // two bytes of the next slice tell that the picture is done
+    assert_eq!(
+        assembler.push(&[0, 0, 0, 1, SLICE[0], SLICE[1]]),
+        [access_unit(&[&SPS, &PPS, &IDR])]
+    );
+    assert!(assembler.push(&SLICE[2..]).is_empty());
+    assert_eq!(assembler.finish(), [access_unit(&[&SLICE])]);
+}
+
+#[test]
+fn primes_with_the_parameter_sets_and_the_frames_since_the_latest_keyframe() {
+    let keyframe = access_unit(&[&SPS, &PPS, &IDR, &IDR_PART]);
+    let slice = access_unit(&[&SLICE]);
+    let mut cache = StreamCache::new();
+
+    // nothing to start from before a keyframe
+    cache.update(&slice);
+    assert_eq!(cache.primer(), None);
+
+    cache.update(&keyframe);
+    assert_eq!(
+        cache.primer(),
+        Some(annex_b(&[&SPS, &PPS, &IDR, &IDR_PART]))
+    );
+
+    // the frames after the keyframe refer to it and to each other
+    cache.update(&slice);
+    cache.update(&access_unit(&[&SLICE, &SLICE_PART]));
+    assert_eq!(
+        cache.primer(),
+        Some(annex_b(&[
+            &SPS,
+            &PPS,
+            &IDR,
+            &IDR_PART,
+            &SLICE,
+            &SLICE,
+            &SLICE_PART
+        ]))
+    );
+
+    // parameter sets sent on their own are used with the next keyframe,
+    // which starts over
+    let sps: &[u8] = &[0x67, 0x42, 0xc0, 0x28];
+    let idr: &[u8] = &[0x65, 0x88, 0x99];
+    cache.update(&access_unit(&[sps]));
+    cache.update(&access_unit(&[idr]));
+    assert_eq!(cache.primer(), Some(annex_b(&[sps, &PPS, idr])));
+}
+
+#[test]
+fn waits_for_a_keyframe_after_too_many_frames() {
+    let mut cache = StreamCache::new();
+    cache.update(&access_unit(&[&SPS, &PPS, &IDR]));
+    for _ in 0..300 {
+        cache.update(&access_unit(&[&SLICE]));
+    }
+    assert_eq!(cache.primer(), None);
+
+    cache.update(&access_unit(&[&IDR]));
+    assert_eq!(cache.primer(), Some(annex_b(&[&SPS, &PPS, &IDR])));
+}
@@ -0,0 +1,170 @@
+use tello_autopilot::{
+    flight::FlightStatus,
+    pose::{wrap_yaw, Pose, PoseEstimator},
+    state::{PointState, State},
+};
+
+const STEP_MS: u64 = 100;
+// `tof` and `h` on the ground and at the takeoff height
+const GROUND: (usize, usize) = (10, 0);
+const HOVER: (usize, usize) = (90, 80);
+
+/// A state at `yaw` moving at `speed` (forward, left, up) in dm/s.
+fn state(yaw: isize, speed: (f32, f32, f32), (tof, height): (usize, usize)) -> State {
+    State {
+        yaw,
+        speeds: PointState {
+            x: speed.0,
+            y: speed.1,
+            z: speed.2,
+        },
+        time_of_flight: tof,
+        height,
+        ..State::default()
+    }
+}
+
+struct Feed {
+    estimator: PoseEstimator,
+    now_ms: u64,
+}
+
+impl Feed {
+    fn new() -> Self {
+        Self {
+            estimator: PoseEstimator::new(),
+            now_ms: 0,
+        }
+    }
+
+    fn push(&mut self, state: &State, flight: FlightStatus) -> Pose {
+        self.now_ms += STEP_MS;
+        self.estimator.update(state, flight, self.now_ms)
+    }
+
+    fn fly(&mut self, state: &State) -> Pose {
+        self.push(state, FlightStatus::Airborne)
+    }
+
+    /// Takes off facing `yaw`.
+    fn take_off(&mut self, yaw: isize) -> Pose {
+        self.push(&state(yaw, (0.0, 0.0, 0.0), GROUND), FlightStatus::Grounded);
+        self.fly(&state(yaw, (0.0, 0.0, 0.0), HOVER))
+    }
+
+    /// Moves 1m at 1m/s along the body axis `speed` points to, at `yaw`.
+    fn move_1m(&mut self, yaw: isize, speed: (f32, f32, f32)) -> Pose {
+        for _ in 0..10 {
+            self.fly(&state(yaw, speed, HOVER));
+        }
+        self.fly(&state(yaw, (0.0, 0.0, 0.0), HOVER))
+    }
+}
+
+fn assert_near(pose: Pose, (x, y, z): (f64, f64, f64), yaw: f64) {
+    let error = ((pose.x - x).powi(2) + (pose.y - y).powi(2) + (pose.z - z).powi(2)).sqrt();
+    assert!(error < 1e-6, "{:?} is not at ({}, {}, {})", pose, x, y, z);
+    assert!(
+        (pose.yaw - yaw).abs() < 1e-6,
+        "{:?} is not at {}°",
+        pose,
+        yaw
+    );
+}
+
+#[test]
+fn turns_body_speeds_into_the_takeoff_frame() {
+    let mut feed = Feed::new();
+    assert_near(feed.take_off(30), (0.0, 0.0, 80.0), 0.0);
+
+    let pose = feed.move_1m(30, (10.0, 0.0, 0.0));
+    assert_near(pose, (100.0, 0.0, 80.0), 0.0);
+
+    // turned right: forward is -y and left is +x
+    let pose = feed.move_1m(120, (10.0, 0.0, 0.0));
+    assert_near(pose, (100.0, -100.0, 80.0), 90.0);
+    let pose = feed.move_1m(120, (0.0, 10.0, 0.0));
+    assert_near(pose, (200.0, -100.0, 80.0), 90.0);
+
+    // the heading wraps around
+    let mut feed = Feed::new();
+    feed.take_off(170);
+    let pose = feed.move_1m(-170, (10.0, 0.0, 0.0));
+    let (sin, cos) = 20f64.to_radians().sin_cos();
+    assert_near(pose, (100.0 * cos, -100.0 * sin, 80.0), 20.0);
+}
+
+#[test]
+fn pulls_the_height_towards_the_sensors() {
+    let mut feed = Feed::new();
+    feed.take_off(0);
+
+    // a climb the range sensor does not see settles 10cm above it, not 5m
+    for _ in 0..50 {
+        feed.fly(&state(0, (0.0, 0.0, 10.0), HOVER));
+    }
+    let pose = feed.fly(&state(0, (0.0, 0.0, 10.0), HOVER));
+    assert!((pose.z - 90.0).abs() < 1e-6, "{:?}", pose);
+
+    // `h` once the ground is out of range
+    for _ in 0..20 {
+        feed.fly(&state(0, (0.0, 0.0, 0.0), (6553, 150)));
+    }
+    let pose = feed.fly(&state(0, (0.0, 0.0, 0.0), (6553, 150)));
+    assert!((pose.z - 150.0).abs() < 1e-3, "{:?}", pose);
+}
+
+#[test]
+fn skips_gaps_in_the_states() {
+    let mut feed = Feed::new();
+    feed.take_off(0);
+    feed.fly(&state(0, (10.0, 0.0, 0.0), HOVER));
+
+    // two seconds without states are not integrated over
+    feed.now_ms += 2000;
+    let pose = feed.fly(&state(0, (10.0, 0.0, 0.0), HOVER));
+    assert_near(pose, (5.0, 0.0, 80.0), 0.0);
+}
+
+#[test]
+fn resets_on_takeoff() {
+    let mut feed = Feed::new();
+    feed.take_off(0);
+    feed.move_1m(-90, (10.0, 0.0, 0.0));
+    let pose = feed.push(&state(-90, (0.0, 0.0, 0.0), GROUND), FlightStatus::Grounded);
+    assert_near(pose, (0.0, 100.0, 0.0), -90.0);
+
+    // moved by hand on the ground
+    feed.push(&state(-60, (0.0, 0.0, 0.0), GROUND), FlightStatus::Grounded);
+    // the heading at this takeoff is the new zero
+    assert_near(feed.take_off(-60), (0.0, 0.0, 80.0), 0.0);
+}
+
+#[test]
+fn follows_the_flight_status() {
+    let mut feed = Feed::new();
+    feed.take_off(0);
+    feed.move_1m(0, (10.0, 0.0, 0.0));
+
+    // `land` sent, still in the air
+    let pose = feed.push(&state(0, (0.0, 0.0, 0.0), HOVER), FlightStatus::Landing);
+    assert_near(pose, (100.0, 0.0, 80.0), 0.0);
+    // answered, whatever `h` says
+    let pose = feed.push(&state(0, (0.0, 0.0, 0.0), HOVER), FlightStatus::Grounded);
+    assert_near(pose, (100.0, 0.0, 0.0), 0.0);
+
+    // `takeoff` sent, still on the ground
+    let pose = feed.push(&state(0, (0.0, 0.0, 0.0), GROUND), FlightStatus::TakingOff);
+    assert_near(pose, (0.0, 0.0, 0.0), 0.0);
+}
+
+#[test]
+fn moves_and_turns_a_pose() {
+    let pose = Pose::default().rotate(90.0).offset(100.0, 50.0, 20.0);
+    assert_near(pose, (50.0, -100.0, 20.0), 90.0);
+
+    assert_near(pose.rotate(100.0), (50.0, -100.0, 20.0), -170.0);
+    assert_near(pose.rotate(-270.0), (50.0, -100.0, 20.0), -180.0);
+    assert_eq!(wrap_yaw(540.0), -180.0);
+    assert_eq!(wrap_yaw(-190.0), 170.0);
+}
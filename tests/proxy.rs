@@ -0,0 +1,232 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use serde_json::{json, Value};
+use tello_autopilot::sim::{self, Simulator};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    spawn,
+    time::{sleep, timeout, Duration},
+};
+
+const RES_TIMEOUT: Duration = Duration::from_millis(200);
+
+async fn rpc(client: &mut LineClient, req: Value) -> Value {
+    client.send(&req.to_string()).await;
+    serde_json::from_str(&client.recv().await).unwrap()
+}
+
+#[tokio::test]
+async fn forwards_commands_in_sdk_format() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start_proxy(drone.addr, RES_TIMEOUT).await;
+    let mut client = LineClient::connect(proxy.cmd).await;
+
+    assert_eq!(client.request("command").await, "ok");
+    assert_eq!(client.request("  forward   50 ").await, "ok");
+    assert_eq!(client.request("battery?").await, "87");
+
+    assert_eq!(drone.next_command().await, "command");
+    assert_eq!(drone.next_command().await, "forward 50");
+    assert_eq!(drone.next_command().await, "battery?");
+}
+
+#[tokio::test]
+async fn routes_responses_to_their_client() {
+    let drone = fake_drone(|cmd| match cmd {
+        // the slow one is queued first, the fast one must not get its answer
+        "speed?" => Reply::After(Duration::from_millis(100), "100.0"),
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let proxy = start_proxy(drone.addr, RES_TIMEOUT).await;
+
+    let mut clients = Vec::new();
+    for (cmd, res) in [("speed?", "100.0"), ("battery?", "87"), ("sdk?", "30")] {
+        let addr = proxy.cmd;
+        clients.push(spawn(async move {
+            let mut client = LineClient::connect(addr).await;
+            for _ in 0..3 {
+                assert_eq!(client.request(cmd).await, res);
+            }
+        }));
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    for client in clients {
+        client.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn times_out_and_drops_late_responses() {
+    let drone = fake_drone(|cmd| match cmd {
+        "sn?" => Reply::After(Duration::from_millis(300), "0TQDG"),
+        "wifi?" => Reply::Never,
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let proxy = start_proxy(drone.addr, RES_TIMEOUT).await;
+    let mut client = LineClient::connect(proxy.cmd).await;
+
+    assert_eq!(
+        client.request("wifi?").await,
+        "error timed out waiting response"
+    );
+    assert_eq!(
+        client.request("sn?").await,
+        "error timed out waiting response"
+    );
+
+    // the late serial number arrives before the next command and is dropped
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(client.request("battery?").await, "87");
+}
+
+#[tokio::test]
+async fn rejects_invalid_commands_without_forwarding() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start_proxy(drone.addr, RES_TIMEOUT).await;
+    let mut client = LineClient::connect(proxy.cmd).await;
+
+    assert_eq!(
+        client.request("forward 5000").await,
+        "error forward: 5000 is out of range, must be 20 ~ 500"
+    );
+    assert!(client.request("dance").await.starts_with("error "));
+    assert_eq!(client.request("land").await, "ok");
+
+    assert_eq!(drone.next_command().await, "land");
+}
+
+#[tokio::test]
+async fn rc_has_no_response() {
+    let mut drone = fake_drone(|cmd| match cmd {
+        cmd if cmd.starts_with("rc ") => Reply::Never,
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let proxy = start_proxy(drone.addr, RES_TIMEOUT).await;
+    let mut client = LineClient::connect(proxy.cmd).await;
+
+    client.send("rc 0 10 0 0").await;
+    assert_eq!(client.request("battery?").await, "87");
+
+    assert_eq!(drone.next_command().await, "rc 0 10 0 0");
+    assert_eq!(drone.next_command().await, "battery?");
+}
+
+#[tokio::test]
+async fn serves_legacy_framing() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start_proxy(drone.addr, RES_TIMEOUT).await;
+    let mut stream = TcpStream::connect(proxy.legacy_cmd).await.unwrap();
+
+    // failures are a bare `error`, like the original protocol
+    stream
+        .write_all(b"commandAbattery?AflyAforward 10A")
+        .await
+        .unwrap();
+
+    let expected = b"ok87\r\nerrorerror";
+    let mut res = Vec::new();
+    let mut buf = [0; 64];
+    while res.len() < expected.len() {
+        let size = timeout(WAIT, stream.read(&mut buf)).await.unwrap().unwrap();
+        assert_ne!(size, 0);
+        res.extend_from_slice(&buf[..size]);
+    }
+
+    assert_eq!(res, expected);
+    assert_eq!(drone.next_command().await, "command");
+    assert_eq!(drone.next_command().await, "battery?");
+}
+
+#[tokio::test]
+async fn serves_json_requests() {
+    let drone = fake_drone(|cmd| match cmd {
+        "wifi?" => Reply::Never,
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let proxy = start_proxy(drone.addr, RES_TIMEOUT).await;
+    let mut client = LineClient::connect(proxy.rpc).await;
+
+    let res = rpc(&mut client, json!({"id": 1, "cmd": {"forward": 50}})).await;
+    assert_eq!(res["id"], 1);
+    assert_eq!(res["result"], "ok");
+    assert!(res["latency_ms"].is_u64());
+
+    let res = rpc(&mut client, json!({"id": "b", "cmd": "read_battery"})).await;
+    assert_eq!(res["result"], json!({"battery": 87}));
+
+    let res = rpc(&mut client, json!({"id": 3, "cmd": {"forward": 5000}})).await;
+    assert_eq!(res["id"], 3);
+    assert!(res["error"].as_str().unwrap().contains("out of range"));
+
+    let res = rpc(&mut client, json!({"id": 4, "cmd": "read_wifi"})).await;
+    assert_eq!(res["error"], "timed out waiting response");
+}
+
+#[tokio::test]
+async fn flies_the_simulator() {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let drone = socket.local_addr().unwrap();
+    let simulator = Arc::new(Mutex::new(Simulator::new()));
+    spawn(sim::serve(socket, 0, simulator.clone()));
+
+    let proxy = start_proxy(drone, Duration::from_secs(5)).await;
+    let mut client = LineClient::connect(proxy.cmd).await;
+
+    assert_eq!(client.request("command").await, "ok");
+    assert_eq!(client.request("forward 50").await, "error Motor stop");
+    assert_eq!(client.request("takeoff").await, "ok");
+    assert_eq!(client.request("up 20").await, "ok");
+    assert_eq!(client.request("battery?").await, "100");
+
+    let (_, _, z) = simulator.lock().unwrap().position();
+    assert!((z - 100.0).abs() < 1.0, "height is {}", z);
+}
+
+#[tokio::test]
+async fn keeps_the_drone_awake_when_idle() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy =
+        start_proxy_with_keepalive(drone.addr, RES_TIMEOUT, Some(Duration::from_millis(200))).await;
+    let mut client = LineClient::connect(proxy.cmd).await;
+
+    // nothing before the first command, the drone may not be there yet
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(client.request("command").await, "ok");
+    assert_eq!(drone.next_command().await, "command");
+
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(drone.next_command().await, "battery?");
+    assert_eq!(drone.next_command().await, "battery?");
+
+    // keepalive responses never reach a client
+    assert_eq!(client.request("speed?").await, "100.0");
+}
+
+#[tokio::test]
+async fn no_keepalive_while_clients_are_busy() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy =
+        start_proxy_with_keepalive(drone.addr, RES_TIMEOUT, Some(Duration::from_millis(300))).await;
+    let mut client = LineClient::connect(proxy.cmd).await;
+
+    for _ in 0..8 {
+        assert_eq!(client.request("sdk?").await, "30");
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    for _ in 0..8 {
+        assert_eq!(drone.next_command().await, "sdk?");
+    }
+    assert!(timeout(Duration::from_millis(100), drone.received.recv())
+        .await
+        .is_err());
+}
@@ -0,0 +1,215 @@
+use std::{io, path::PathBuf};
+
+use tello_autopilot::{
+    h264::{AccessUnit, NalUnit, StreamCache},
+    recorder::Recorder,
+    replay::{load_pcap, load_recording, replay, Packet, ReplayError, Stream},
+    state::{State, StateFrame},
+};
+use tokio::{
+    net::UdpSocket,
+    spawn,
+    time::{sleep, timeout, Duration, Instant},
+};
+
+const WAIT: Duration = Duration::from_secs(2);
+const STATE: &[u8] = b"pitch:0;roll:0;yaw:90;vgx:0;vgy:0;vgz:0;templ:83;temph:85;tof:10;h:0;bat:70;baro:152.82;time:0;agx:-2.00;agy:-3.00;agz:-999.00;\r\n";
+const VIDEO: &[u8] = &[0, 0, 0, 1, 0x65, 0x88, 0x84];
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tello-replay-{}-{}", std::process::id(), name))
+}
+
+/// An Ethernet frame carrying a UDP datagram from the drone to `dst_port`.
+fn udp_frame(dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    let udp_len = 8 + payload.len() as u16;
+    let mut frame = vec![0; 12];
+    frame.extend([0x08, 0x00]);
+    // IPv4 without options, UDP
+    frame.extend([0x45, 0]);
+    frame.extend((20 + udp_len).to_be_bytes());
+    frame.extend([0, 0, 0, 0, 64, 17, 0, 0]);
+    frame.extend([192, 168, 10, 1, 192, 168, 10, 2]);
+    frame.extend(8889u16.to_be_bytes());
+    frame.extend(dst_port.to_be_bytes());
+    frame.extend(udp_len.to_be_bytes());
+    frame.extend([0, 0]);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// A classic pcap of Ethernet `records` `(seconds, fraction, frame)`, with the
+/// fraction in µs or ns.
+fn pcap(big_endian: bool, nanos: bool, records: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+    let u32_bytes = |v: u32| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+    let u16_bytes = |v: u16| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+
+    let mut data = Vec::new();
+    data.extend(u32_bytes(if nanos { 0xa1b23c4d } else { 0xa1b2c3d4 }));
+    data.extend(u16_bytes(2));
+    data.extend(u16_bytes(4));
+    // time zone, accuracy, snapshot length, Ethernet
+    for v in [0, 0, 65535, 1] {
+        data.extend(u32_bytes(v));
+    }
+
+    for (sec, frac, frame) in records {
+        for v in [*sec, *frac, frame.len() as u32, frame.len() as u32] {
+            data.extend(u32_bytes(v));
+        }
+        data.extend(frame);
+    }
+    data
+}
+
+fn load(name: &str, data: &[u8]) -> Result<Vec<Packet>, ReplayError> {
+    let path = temp_path(name);
+    std::fs::write(&path, data).unwrap();
+    let packets = load_pcap(&path);
+    std::fs::remove_file(&path).unwrap();
+    packets
+}
+
+#[test]
+fn loads_pcaps_of_both_byte_orders() {
+    let expected = vec![
+        Packet {
+            time: Duration::ZERO,
+            stream: Stream::State,
+            data: STATE.to_vec(),
+        },
+        Packet {
+            time: Duration::from_millis(250),
+            stream: Stream::Video,
+            data: VIDEO.to_vec(),
+        },
+    ];
+
+    for (big_endian, nanos, frac_per_ms) in [(false, false, 1000), (true, true, 1_000_000)] {
+        let mut data = pcap(
+            big_endian,
+            nanos,
+            &[
+                (10, 500 * frac_per_ms, udp_frame(8890, STATE)),
+                // responses to commands are not replayed
+                (10, 600 * frac_per_ms, udp_frame(8889, b"ok")),
+                (10, 750 * frac_per_ms, udp_frame(11111, VIDEO)),
+                // cut off while writing
+                (11, 0, udp_frame(8890, STATE)),
+            ],
+        );
+        data.truncate(data.len() - 10);
+
+        let name = format!("{}-{}.pcap", big_endian, nanos);
+        assert_eq!(load(&name, &data).unwrap(), expected, "{}", name);
+    }
+}
+
+#[test]
+fn rejects_files_that_are_not_pcaps() {
+    assert!(matches!(
+        load("short.pcap", &[0xd4, 0xc3, 0xb2, 0xa1]),
+        Err(ReplayError::Pcap(_))
+    ));
+    // pcapng
+    assert!(matches!(
+        load(
+            "pcapng.pcap",
+            &[[0x0a, 0x0d, 0x0d, 0x0a], [0; 4]].concat().repeat(3)
+        ),
+        Err(ReplayError::Pcap(_))
+    ));
+}
+
+#[tokio::test]
+async fn replays_a_recording() {
+    let dir = temp_path("recording");
+    let recorder = Recorder::new(&dir);
+    let session = recorder.start().unwrap();
+
+    let state = State::from_str(std::str::from_utf8(STATE).unwrap().trim()).unwrap();
+    let keyframe = AccessUnit {
+        nal_units: [&[0x67, 0x42][..], &[0x68, 0xce], &[0x65, 0x88, 0x84]]
+            .iter()
+            .map(|data| NalUnit::new(data.to_vec()).unwrap())
+            .collect(),
+    };
+    let mut cache = StreamCache::new();
+    cache.update(&keyframe);
+
+    recorder.record_state(&StateFrame::new(0, state.clone()));
+    sleep(Duration::from_millis(100)).await;
+    recorder.record_video(&keyframe, &cache);
+    sleep(Duration::from_millis(100)).await;
+    recorder.record_state(&StateFrame::new(1, state.clone()));
+    recorder.stop().unwrap();
+
+    let packets = load_recording(&session).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let state_data = format!("{}\r\n", state).into_bytes();
+    assert_eq!(
+        packets.iter().map(|p| p.stream).collect::<Vec<_>>(),
+        [Stream::State, Stream::Video, Stream::State]
+    );
+    assert_eq!(packets[0].time, Duration::ZERO);
+    assert!(packets[2].time >= Duration::from_millis(200));
+    assert_eq!(packets[0].data, state_data);
+    assert_eq!(packets[1].data, keyframe.to_annex_b());
+
+    let state_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let video_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let targets = (
+        state_socket.local_addr().unwrap(),
+        video_socket.local_addr().unwrap(),
+    );
+    let length = packets[2].time;
+    let start = Instant::now();
+    let replaying = spawn(async move { replay(&packets, targets.0, targets.1, 2.0).await });
+
+    let mut buf = [0; 1500];
+    for expected in [&state_data, &state_data] {
+        let size = timeout(WAIT, state_socket.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..size], &expected[..]);
+    }
+    let size = timeout(WAIT, video_socket.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&buf[..size], keyframe.to_annex_b());
+
+    timeout(WAIT, replaying).await.unwrap().unwrap().unwrap();
+    // twice as fast
+    let elapsed = start.elapsed();
+    assert!(elapsed >= length / 2, "{:?} of {:?}", elapsed, length);
+    assert!(elapsed < length, "{:?} of {:?}", elapsed, length);
+}
+
+#[tokio::test]
+async fn refuses_invalid_speeds() {
+    let packets = [Packet {
+        time: Duration::from_secs(1),
+        stream: Stream::State,
+        data: STATE.to_vec(),
+    }];
+    let target = "127.0.0.1:9".parse().unwrap();
+
+    for speed in [0.0, -1.0, f64::NAN, f64::NEG_INFINITY, 1e-300] {
+        let e = replay(&packets, target, target, speed).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{}", speed);
+    }
+}
@@ -0,0 +1,300 @@
+mod common;
+
+use common::*;
+use serde_json::Value;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tello_autopilot::{
+    config::SafetyConfig, flight::FlightStatus, safety::SafetyAction, TelloProxy,
+};
+use tokio::{
+    net::UdpSocket,
+    spawn,
+    time::{sleep, timeout, Duration, Instant},
+};
+
+fn config() -> SafetyConfig {
+    SafetyConfig {
+        state_timeout_ms: 300,
+        ..SafetyConfig::default()
+    }
+}
+
+async fn start(drone: &FakeDrone, safety: SafetyConfig) -> TelloProxy {
+    TelloProxy::builder()
+        .listen(loopback_listen())
+        .tello(loopback_tello(drone.addr))
+        .video_subscribers(Vec::new())
+        .res_timeout(Duration::from_millis(200))
+        .keepalive(None)
+        .handshake(false)
+        .record_dir(std::env::temp_dir())
+        .safety(safety)
+        .start()
+        .await
+        .unwrap()
+}
+
+async fn send_state(proxy: &TelloProxy, height: usize, battery: usize, temp_high: usize) {
+    UdpSocket::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .send_to(
+            flight_state_line(height, battery, temp_high).as_bytes(),
+            proxy.tello_addrs().state,
+        )
+        .await
+        .unwrap();
+    // let the supervisor see it
+    sleep(Duration::from_millis(50)).await;
+}
+
+async fn events(proxy: &TelloProxy) -> LineClient {
+    let client = LineClient::connect(proxy.listen_addrs().events).await;
+    sleep(Duration::from_millis(50)).await;
+    client
+}
+
+async fn recv_event(client: &mut LineClient) -> Value {
+    serde_json::from_str(&client.recv().await).unwrap()
+}
+
+/// Skips the empty doorbells of the state and video relays.
+async fn next_command(drone: &mut FakeDrone) -> String {
+    loop {
+        let cmd = drone.next_command().await;
+        if !cmd.is_empty() {
+            return cmd;
+        }
+    }
+}
+
+#[tokio::test]
+async fn refuses_takeoff_on_low_battery() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start(&drone, config()).await;
+    let mut events = events(&proxy).await;
+    let mut client = LineClient::connect(proxy.listen_addrs().cmd).await;
+
+    send_state(&proxy, 0, 5, 60).await;
+    let event = recv_event(&mut events).await;
+    assert_eq!(event["trigger"], "low_battery");
+    assert_eq!(event["battery"], 5);
+    assert_eq!(event["action"], "land");
+    assert_eq!(event["message"], "takeoff refused until cleared");
+
+    assert_eq!(
+        client.request("takeoff").await,
+        "error refused: low battery (5%)"
+    );
+    assert_eq!(recv_event(&mut events).await["message"], "refused takeoff");
+    assert_eq!(client.request("battery?").await, "87");
+    assert_eq!(next_command(&mut drone).await, "battery?");
+
+    // charged again
+    send_state(&proxy, 0, 80, 60).await;
+    assert_eq!(client.request("takeoff").await, "ok");
+    assert_eq!(next_command(&mut drone).await, "takeoff");
+}
+
+#[tokio::test]
+async fn lands_on_low_battery_in_flight() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start(&drone, config()).await;
+    let mut events = events(&proxy).await;
+
+    send_state(&proxy, 100, 50, 60).await;
+    send_state(&proxy, 100, 9, 60).await;
+    // once
+    send_state(&proxy, 100, 8, 60).await;
+
+    let event = recv_event(&mut events).await;
+    assert_eq!(event["trigger"], "low_battery");
+    assert_eq!(event["message"], "landing");
+    assert_eq!(next_command(&mut drone).await, "land");
+    assert!(timeout(Duration::from_millis(200), drone.received.recv())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn lands_again_until_the_drone_is_down() {
+    let lands = Arc::new(AtomicUsize::new(0));
+    let drone_lands = lands.clone();
+    let mut drone = fake_drone(move |cmd| match cmd {
+        "land" if drone_lands.fetch_add(1, Ordering::SeqCst) == 0 => Reply::Now("error"),
+        _ => tello_replies(cmd),
+    })
+    .await;
+    let proxy = start(&drone, config()).await;
+
+    send_state(&proxy, 100, 50, 60).await;
+    send_state(&proxy, 100, 8, 60).await;
+    assert_eq!(next_command(&mut drone).await, "land");
+    let failed_at = Instant::now();
+
+    // the drone is kept flying by the states until it lands
+    let keep_flying = {
+        let proxy_state = proxy.tello_addrs().state;
+        spawn(async move {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            loop {
+                let line = flight_state_line(100, 8, 60);
+                socket.send_to(line.as_bytes(), proxy_state).await.unwrap();
+                sleep(Duration::from_millis(100)).await;
+            }
+        })
+    };
+    assert_eq!(next_command(&mut drone).await, "land");
+    assert!(failed_at.elapsed() >= Duration::from_millis(900));
+    keep_flying.abort();
+
+    // answered `ok` this time
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(proxy.flight().status(), FlightStatus::Grounded);
+    assert!(timeout(Duration::from_millis(1500), drone.received.recv())
+        .await
+        .is_err());
+    assert_eq!(lands.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn ignores_the_battery_flickering_at_the_limit() {
+    let drone = fake_drone(tello_replies).await;
+    let proxy = start(&drone, config()).await;
+    let mut events = events(&proxy).await;
+    let mut client = LineClient::connect(proxy.listen_addrs().cmd).await;
+
+    send_state(&proxy, 0, 9, 60).await;
+    assert_eq!(recv_event(&mut events).await["trigger"], "low_battery");
+    for battery in [10, 12, 9, 11] {
+        send_state(&proxy, 0, battery, 60).await;
+    }
+    assert!(timeout(Duration::from_millis(200), events.recv())
+        .await
+        .is_err());
+    assert_eq!(
+        client.request("takeoff").await,
+        "error refused: low battery (11%)"
+    );
+
+    send_state(&proxy, 0, 13, 60).await;
+    assert_eq!(client.request("takeoff").await, "ok");
+}
+
+#[tokio::test]
+async fn takes_the_configured_action() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start(
+        &drone,
+        SafetyConfig {
+            temperature_action: SafetyAction::Warn,
+            ..config()
+        },
+    )
+    .await;
+    let mut events = events(&proxy).await;
+    let mut client = LineClient::connect(proxy.listen_addrs().cmd).await;
+
+    send_state(&proxy, 100, 50, 95).await;
+
+    let event = recv_event(&mut events).await;
+    assert_eq!(event["trigger"], "high_temperature");
+    assert_eq!(event["temperature"], 95);
+    assert_eq!(event["action"], "warn");
+    assert_eq!(client.request("flip l").await, "ok");
+    assert_eq!(next_command(&mut drone).await, "flip l");
+}
+
+#[tokio::test]
+async fn lands_when_the_states_stop() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start(&drone, config()).await;
+    let mut events = events(&proxy).await;
+
+    send_state(&proxy, 100, 50, 60).await;
+
+    let event = recv_event(&mut events).await;
+    assert_eq!(event["trigger"], "state_timeout");
+    assert!(event["since_ms"].as_u64().unwrap() >= 300);
+    assert_eq!(next_command(&mut drone).await, "land");
+    assert!(proxy.safety().active().len() == 1);
+
+    send_state(&proxy, 0, 50, 60).await;
+    assert!(proxy.safety().active().is_empty());
+}
+
+#[tokio::test]
+async fn lands_when_the_last_client_disconnects_in_flight() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start(
+        &drone,
+        SafetyConfig {
+            state_timeout_ms: 0,
+            link_lost_grace_ms: 0,
+            ..config()
+        },
+    )
+    .await;
+    let mut events = events(&proxy).await;
+
+    // leaving while landed is fine
+    drop(LineClient::connect(proxy.listen_addrs().cmd).await);
+    sleep(Duration::from_millis(50)).await;
+
+    let first = LineClient::connect(proxy.listen_addrs().cmd).await;
+    let second = LineClient::connect(proxy.listen_addrs().rpc).await;
+    send_state(&proxy, 100, 50, 60).await;
+
+    drop(first);
+    sleep(Duration::from_millis(100)).await;
+    assert!(proxy.safety().active().is_empty());
+
+    drop(second);
+    let event = recv_event(&mut events).await;
+    assert_eq!(event["trigger"], "link_lost");
+    assert_eq!(event["message"], "landing");
+    assert_eq!(next_command(&mut drone).await, "land");
+}
+
+#[tokio::test]
+async fn waits_for_clients_to_reconnect() {
+    let mut drone = fake_drone(tello_replies).await;
+    let proxy = start(
+        &drone,
+        SafetyConfig {
+            state_timeout_ms: 0,
+            link_lost_grace_ms: 300,
+            ..config()
+        },
+    )
+    .await;
+    let mut events = events(&proxy).await;
+    send_state(&proxy, 100, 50, 60).await;
+
+    // one connection per command
+    for _ in 0..5 {
+        let mut client = LineClient::connect(proxy.listen_addrs().cmd).await;
+        assert_eq!(client.request("battery?").await, "87");
+        assert_eq!(next_command(&mut drone).await, "battery?");
+        drop(client);
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(proxy.safety().active().is_empty());
+
+    // gone for good
+    let event = timeout(Duration::from_secs(1), recv_event(&mut events))
+        .await
+        .unwrap();
+    assert_eq!(event["trigger"], "link_lost");
+    assert_eq!(event["message"], "landing");
+    assert_eq!(next_command(&mut drone).await, "land");
+
+    // cleared by the next client
+    let _client = LineClient::connect(proxy.listen_addrs().cmd).await;
+    sleep(Duration::from_millis(50)).await;
+    assert!(proxy.safety().active().is_empty());
+}
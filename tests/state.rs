@@ -0,0 +1,190 @@
+mod common;
+
+use common::*;
+use tello_autopilot::state::{MissionpadState, PointState, State, StateFrame};
+use tokio::{
+    net::UdpSocket,
+    time::{sleep, Duration},
+};
+
+// as the drone sends them: without mission pads, and with `mon` (SDK 2.0)
+const STATE_LINE: &str = "pitch:1;roll:-2;yaw:-45;vgx:3;vgy:0;vgz:-1;templ:83;temph:85;tof:78;h:70;bat:72;baro:152.82;time:12;agx:-2.00;agy:-3.00;agz:-999.00;\r\n";
+const MISSIONPAD_LINE: &str = "mid:3;x:23;y:-8;z:65;mpry:1,-2,45;pitch:1;roll:-2;yaw:-45;vgx:3;vgy:0;vgz:-1;templ:83;temph:85;tof:78;h:70;bat:72;baro:152.82;time:12;agx:-2.00;agy:-3.00;agz:-999.00;\r\n";
+
+async fn recv_frame(client: &mut LineClient) -> StateFrame {
+    serde_json::from_str(&client.recv().await).unwrap()
+}
+
+fn flight_fields() -> State {
+    State {
+        missionpad: None,
+        pitch: 1,
+        roll: -2,
+        yaw: -45,
+        speeds: PointState {
+            x: 3.0,
+            y: 0.0,
+            z: -1.0,
+        },
+        temp_low: 83,
+        temp_high: 85,
+        time_of_flight: 78,
+        height: 70,
+        battery: 72,
+        barometer: 152.82,
+        time: 12,
+        accelerations: PointState {
+            x: -2.0,
+            y: -3.0,
+            z: -999.0,
+        },
+    }
+}
+
+#[test]
+fn parses_a_state_without_missionpad() {
+    let state = State::from_str(STATE_LINE).unwrap();
+    assert_eq!(state, flight_fields());
+    assert_eq!(State::from_str(&state.to_string()), Some(state));
+}
+
+#[test]
+fn parses_a_state_with_missionpad() {
+    let state = State::from_str(MISSIONPAD_LINE).unwrap();
+    let pad = MissionpadState {
+        mid: 3,
+        x: 23,
+        y: -8,
+        z: 65,
+        pitch: 1,
+        roll: -2,
+        yaw: 45,
+    };
+    assert_eq!(state.missionpad, Some(pad));
+    assert!(pad.is_detected());
+    // the pad fields do not leak into the flight fields
+    assert_eq!(
+        state,
+        State {
+            missionpad: Some(pad),
+            ..flight_fields()
+        }
+    );
+    assert_eq!(State::from_str(&state.to_string()), Some(state));
+}
+
+#[test]
+fn parses_a_state_without_a_detected_missionpad() {
+    let line = MISSIONPAD_LINE.replace(
+        "mid:3;x:23;y:-8;z:65;mpry:1,-2,45;",
+        "mid:-1;x:-100;y:-100;z:-100;mpry:0,0,0;",
+    );
+    let pad = State::from_str(&line).unwrap().missionpad.unwrap();
+    assert_eq!(pad, MissionpadState::default());
+    assert!(!pad.is_detected());
+}
+
+#[test]
+fn rejects_malformed_states() {
+    // a field short of either form
+    assert_eq!(
+        State::from_str(&STATE_LINE.replacen("pitch:1;", "", 1)),
+        None
+    );
+    assert_eq!(
+        State::from_str(&MISSIONPAD_LINE.replace("mpry:1,-2,45", "mpry:1,-2")),
+        None
+    );
+    assert_eq!(
+        State::from_str(&MISSIONPAD_LINE.replace("x:23", "x:a")),
+        None
+    );
+    assert_eq!(State::from_str("ok"), None);
+}
+
+#[tokio::test]
+async fn streams_states_as_ndjson() {
+    let relay = start_state_relay().await;
+    let mut client = LineClient::connect(relay.tcp).await;
+    // let the relay subscribe the client before the drone starts sending
+    sleep(Duration::from_millis(100)).await;
+
+    let drone = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    for (yaw, battery) in [(10, 90), (20, 89), (30, 88)] {
+        drone
+            .send_to(state_line(yaw, battery).as_bytes(), relay.udp)
+            .await
+            .unwrap();
+    }
+
+    for (seq, (yaw, battery)) in [(10, 90), (20, 89), (30, 88)].into_iter().enumerate() {
+        let frame = recv_frame(&mut client).await;
+        assert_eq!(frame.seq, seq as u64);
+        assert_eq!(frame.state.yaw, yaw);
+        assert_eq!(frame.state.battery, battery);
+        assert!(frame.timestamp_ms > 0);
+    }
+}
+
+#[tokio::test]
+async fn skips_invalid_states() {
+    let relay = start_state_relay().await;
+    let mut client = LineClient::connect(relay.tcp).await;
+    sleep(Duration::from_millis(100)).await;
+
+    let drone = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    drone.send_to(b"ok", relay.udp).await.unwrap();
+    drone.send_to(b"pitch:0;roll:x;", relay.udp).await.unwrap();
+    drone
+        .send_to(state_line(45, 50).as_bytes(), relay.udp)
+        .await
+        .unwrap();
+
+    // garbage does not count as a dropped state
+    let frame = recv_frame(&mut client).await;
+    assert_eq!(frame.seq, 0);
+    assert_eq!(frame.state.yaw, 45);
+}
+
+#[tokio::test]
+async fn fans_out_to_every_client() {
+    let relay = start_state_relay().await;
+    let mut first = LineClient::connect(relay.tcp).await;
+    let mut second = LineClient::connect(relay.tcp).await;
+    sleep(Duration::from_millis(100)).await;
+
+    let drone = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    for yaw in 0..5 {
+        drone
+            .send_to(state_line(yaw, 80).as_bytes(), relay.udp)
+            .await
+            .unwrap();
+    }
+
+    for yaw in 0..5 {
+        let a = recv_frame(&mut first).await;
+        let b = recv_frame(&mut second).await;
+        assert_eq!(a, b);
+        assert_eq!(a.state.yaw, yaw);
+    }
+}
+
+#[tokio::test]
+async fn publishes_the_pose() {
+    let relay = start_state_relay().await;
+    let mut client = LineClient::connect(relay.tcp).await;
+    sleep(Duration::from_millis(100)).await;
+
+    let drone = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    for height in [0, 100] {
+        drone
+            .send_to(flight_state_line(height, 80, 60).as_bytes(), relay.udp)
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(recv_frame(&mut client).await.pose.z, 0.0);
+    // from the distance sensor, above its reading on the ground
+    let pose = recv_frame(&mut client).await.pose;
+    assert_eq!((pose.x, pose.y, pose.z), (0.0, 0.0, 100.0));
+}
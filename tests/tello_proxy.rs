@@ -0,0 +1,155 @@
+mod common;
+
+use common::*;
+use tello_autopilot::{
+    cmd::Command,
+    proxy::DispatchError,
+    recorder::{CommandRecord, COMMAND_FILE},
+    TelloProxy,
+};
+use tokio::{
+    io::AsyncReadExt,
+    net::{TcpStream, UdpSocket},
+    spawn,
+    time::{sleep, timeout, Duration},
+};
+
+async fn start(drone: &FakeDrone, handshake: bool) -> TelloProxy {
+    TelloProxy::builder()
+        .listen(loopback_listen())
+        .tello(loopback_tello(drone.addr))
+        .video_subscribers(Vec::new())
+        .res_timeout(Duration::from_millis(200))
+        .record_dir(std::env::temp_dir())
+        .handshake(handshake)
+        .start()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn enters_sdk_mode_before_streaming() {
+    let mut drone = fake_drone(tello_replies).await;
+    let _proxy = start(&drone, true).await;
+
+    // the doorbells of the state and video relays come first
+    let mut received = Vec::new();
+    while received.len() < 4 {
+        received.push(drone.next_command().await);
+    }
+    received.retain(|cmd| !cmd.is_empty());
+
+    assert_eq!(received, ["command", "streamon"]);
+}
+
+#[tokio::test]
+async fn serves_every_port() {
+    let drone = fake_drone(tello_replies).await;
+    let proxy = start(&drone, false).await;
+    let listen = proxy.listen_addrs().clone();
+
+    let mut cmd = LineClient::connect(listen.cmd).await;
+    assert_eq!(cmd.request("battery?").await, "87");
+    assert_eq!(
+        proxy.send(Command::ReadSpeed).await.unwrap().unwrap(),
+        "100.0\r\n"
+    );
+
+    let mut record = LineClient::connect(listen.record_control).await;
+    assert_eq!(record.request("status").await, "idle");
+
+    let mut video = LineClient::connect(listen.video_control).await;
+    assert_eq!(video.request("list").await, "[]");
+
+    let mut states = proxy.subscribe_states();
+    let mut state = LineClient::connect(listen.state).await;
+    sleep(Duration::from_millis(100)).await;
+    UdpSocket::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .send_to(state_line(90, 70).as_bytes(), proxy.tello_addrs().state)
+        .await
+        .unwrap();
+
+    assert_eq!(states.recv().await.unwrap().state.yaw, 90);
+    assert!(state.recv().await.contains("\"yaw\":90"));
+}
+
+#[tokio::test]
+async fn records_commands() {
+    let drone = fake_drone(|cmd| match cmd {
+        "battery?" => Reply::After(Duration::from_millis(100), "87\r\n"),
+        "speed?" => Reply::Never,
+        _ => Reply::Now("ok"),
+    })
+    .await;
+    let dir = std::env::temp_dir().join(format!("tello-commands-{}", std::process::id()));
+    let proxy = TelloProxy::builder()
+        .listen(loopback_listen())
+        .tello(loopback_tello(drone.addr))
+        .video_subscribers(Vec::new())
+        .res_timeout(Duration::from_millis(200))
+        .keepalive(None)
+        .handshake(false)
+        .record_dir(&dir)
+        .record(true)
+        .start()
+        .await
+        .unwrap();
+
+    let mut cmd = LineClient::connect(proxy.listen_addrs().cmd).await;
+    assert_eq!(cmd.request("battery?").await, "87");
+    assert!(cmd.request("speed?").await.starts_with("error"));
+    // grounded
+    assert!(cmd.request("forward 50").await.starts_with("error refused"));
+
+    let session = proxy.recorder().stop().unwrap();
+    let records: Vec<CommandRecord> = std::fs::read_to_string(session.join(COMMAND_FILE))
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let cmds: Vec<_> = records.iter().map(|r| r.cmd.to_string()).collect();
+    assert_eq!(cmds, ["battery?", "speed?", "forward 50"]);
+
+    assert_eq!(records[0].response.as_deref(), Some("87"));
+    assert_eq!(records[0].error, None);
+    assert!(
+        (100..200).contains(&records[0].latency_ms),
+        "{:?}",
+        records[0]
+    );
+
+    assert_eq!(records[1].response, None);
+    assert_eq!(records[1].error, Some(DispatchError::Timeout.to_string()));
+    assert!(records[1].latency_ms >= 200, "{:?}", records[1]);
+
+    assert_eq!(records[2].response, None);
+    assert!(records[2].error.as_ref().unwrap().starts_with("refused"));
+    assert_eq!(records[2].latency_ms, 0);
+}
+
+#[tokio::test]
+async fn stop_closes_ports_and_connections() {
+    let drone = fake_drone(tello_replies).await;
+    let proxy = start(&drone, false).await;
+    let listen = proxy.listen_addrs().clone();
+
+    let mut client = TcpStream::connect(listen.cmd).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+
+    let stop = proxy.stop_handle();
+    let running = spawn(proxy.run());
+    stop.stop();
+    timeout(WAIT, running).await.unwrap().unwrap().unwrap();
+
+    let mut buf = [0; 16];
+    let size = timeout(WAIT, client.read(&mut buf)).await.unwrap();
+    assert!(matches!(size, Ok(0) | Err(_)));
+
+    for addr in [listen.cmd, listen.state, listen.rpc, listen.video_control] {
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
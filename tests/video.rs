@@ -0,0 +1,126 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::*;
+use tello_autopilot::h264::VIDEO_PACKET_SIZE;
+use tokio::{
+    net::UdpSocket,
+    time::{sleep, Duration},
+};
+
+const SPS: u8 = 7;
+const PPS: u8 = 8;
+const IDR: u8 = 5;
+const SLICE: u8 = 1;
+
+/// Sends the frames `range` of `stream` like the drone does, in 1460 byte chunks.
+async fn send_frames(drone: &UdpSocket, relay: SocketAddr, range: std::ops::Range<usize>) {
+    let stream = h264_stream(range.end, 10);
+    let start = h264_stream(range.start, 10).len();
+    for chunk in stream[start..].chunks(VIDEO_PACKET_SIZE) {
+        drone.send_to(chunk, relay).await.unwrap();
+        // don't overrun the socket buffers
+        sleep(Duration::from_micros(200)).await;
+    }
+}
+
+/// Frames as a subscriber sees them, starting with a keyframe at `first`.
+fn frames(first: u8, range: std::ops::Range<u8>) -> Vec<(u8, u8)> {
+    let mut units = vec![(SPS, first), (PPS, first), (IDR, first)];
+    for i in range {
+        if i % 10 == 0 {
+            units.extend([(SPS, i), (PPS, i), (IDR, i)]);
+        } else {
+            units.push((SLICE, i));
+        }
+    }
+    units
+}
+
+fn received_units(packets: &[Vec<u8>]) -> Vec<(u8, u8)> {
+    nal_units(&packets.concat())
+}
+
+#[tokio::test]
+async fn relays_frames_to_static_subscribers() {
+    let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let relay =
+        start_video_relay(&[first.local_addr().unwrap(), second.local_addr().unwrap()]).await;
+
+    let drone = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    send_frames(&drone, relay.udp, 0..25).await;
+
+    // the last frame is held until the next one starts
+    let expected = frames(0, 1..24);
+    let packets = recv_until_quiet(&first).await;
+    assert!(packets.iter().all(|p| p.len() <= VIDEO_PACKET_SIZE));
+    assert_eq!(received_units(&packets), expected);
+    assert_eq!(received_units(&recv_until_quiet(&second).await), expected);
+}
+
+#[tokio::test]
+async fn primes_runtime_subscribers_from_the_latest_keyframe() {
+    let relay = start_video_relay(&[]).await;
+    let drone = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let subscriber = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = subscriber.local_addr().unwrap();
+    let mut control = LineClient::connect(relay.control).await;
+
+    send_frames(&drone, relay.udp, 0..15).await;
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(control.request(&format!("subscribe {}", addr)).await, "ok");
+    let list: serde_json::Value = serde_json::from_str(&control.request("list").await).unwrap();
+    assert_eq!(list[0]["addr"], addr.to_string());
+
+    send_frames(&drone, relay.udp, 15..25).await;
+
+    // the cached keyframe and the frames since, then frame 14, which was
+    // pending when subscribing, and the rest
+    assert_eq!(
+        received_units(&recv_until_quiet(&subscriber).await),
+        frames(10, 11..24)
+    );
+
+    assert_eq!(
+        control.request(&format!("unsubscribe {}", addr)).await,
+        "ok"
+    );
+    send_frames(&drone, relay.udp, 25..30).await;
+    assert!(recv_until_quiet(&subscriber).await.is_empty());
+}
+
+#[tokio::test]
+async fn rejects_invalid_control_requests() {
+    let relay = start_video_relay(&[]).await;
+    let mut control = LineClient::connect(relay.control).await;
+
+    assert!(control
+        .request("subscribe localhost")
+        .await
+        .starts_with("error invalid address"));
+    assert_eq!(
+        control.request("unsubscribe 127.0.0.1:5000").await,
+        "error 127.0.0.1:5000 is not subscribed"
+    );
+
+    // would overflow the expiry
+    assert_eq!(
+        control
+            .request(&format!("subscribe 127.0.0.1:5000 {}", u64::MAX))
+            .await,
+        format!("error ttl {}s is longer than 86400s", u64::MAX)
+    );
+    assert_eq!(
+        control.request("subscribe 127.0.0.1:5000 86401").await,
+        "error ttl 86401s is longer than 86400s"
+    );
+    // the control port still works
+    assert_eq!(
+        control.request("subscribe 127.0.0.1:5000 86400").await,
+        "ok"
+    );
+    assert!(control.request("list").await.contains("127.0.0.1:5000"));
+}